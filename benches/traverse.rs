@@ -0,0 +1,95 @@
+//! Times DOM traversal on `francis_wiki.html`, against the recursive
+//! versions `find_elements` and `collect_text` used to have.
+//!
+//! Run with `cargo bench --bench traverse`.
+
+use francis_wb::cascade::ComputedStyles;
+use francis_wb::dom::{self, get_elem_name};
+use francis_wb::whitespace::{self, WhiteSpace};
+use francis_wb::{source, traverse};
+use html5ever::rcdom::{Handle, NodeData};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 200;
+
+/// Runs `f` `ITERATIONS` times and prints the mean time per run.
+fn bench<T>(name: &str, mut f: impl FnMut() -> T) {
+    // One untimed run to warm the caches.
+    black_box(f());
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    let mean: Duration = start.elapsed() / ITERATIONS;
+    println!("{:<32} {:>10.1} µs", name, mean.as_secs_f64() * 1e6);
+}
+
+fn recursive_find_elements(node: &Handle, elem_name: &str) -> Vec<Handle> {
+    let mut vec: Vec<Handle> = vec![];
+    if get_elem_name(node) == elem_name {
+        vec.push(node.clone());
+    }
+    for child in node.children.borrow().iter() {
+        vec.extend(recursive_find_elements(child, elem_name));
+    }
+    vec
+}
+
+fn recursive_collect_text(node: &Handle) -> String {
+    let mut text = String::new();
+    let computed = ComputedStyles::default();
+    let mode = dom::white_space_of(node, &computed);
+    recursive_collect_text_into(node, mode, &computed, &mut text);
+
+    whitespace::trim(&text).to_string()
+}
+
+fn recursive_collect_text_into(
+    node: &Handle,
+    mode: WhiteSpace,
+    computed: &ComputedStyles,
+    text: &mut String,
+) {
+    if let NodeData::Text { ref contents } = node.data {
+        let processed = whitespace::process(&contents.borrow(), mode);
+        let processed = if mode.collapses_spaces() && text.ends_with(' ') {
+            processed.trim_start_matches(' ')
+        } else {
+            &processed
+        };
+        text.push_str(processed);
+    }
+
+    for child in node.children.borrow().iter() {
+        let child_mode = dom::element_white_space(child, mode, computed);
+        recursive_collect_text_into(child, child_mode, computed, text);
+    }
+}
+
+fn main() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/francis_wiki.html");
+    let html_data = source::load(path).expect("francis_wiki.html");
+    let dom = dom::parse(&html_data);
+    let root = dom::document_element(&dom).unwrap();
+
+    println!(
+        "{} nodes, {} runs each",
+        traverse::descendants(&root).count(),
+        ITERATIONS
+    );
+
+    bench("find_elements (recursive)", || {
+        recursive_find_elements(&root, "td").len()
+    });
+    bench("find_elements", || dom::find_elements(&root, "td").len());
+
+    assert_eq!(recursive_collect_text(&root), dom::collect_text(&root));
+    bench("collect_text (recursive)", || {
+        recursive_collect_text(&root).len()
+    });
+    bench("collect_text", || dom::collect_text(&root).len());
+
+    bench("descendants", || traverse::descendants(&root).count());
+}
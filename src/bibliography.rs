@@ -0,0 +1,333 @@
+//! The citations of an article as CSL-JSON and BibTeX.
+//!
+//! The entries come from the [`Citation`]s of `references::extract`, so
+//! only notes with a `<cite>` in them count. Keys are made from the `<cite>`
+//! ids (`Reference-Maxine_Snowden-2016` becomes `Maxine_Snowden-2016`), or
+//! from the note id if the `<cite>` has none.
+
+use crate::references::{Citation, Reference};
+use serde_json::{json, Map, Value};
+
+/// A citation with the key it is exported under.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry<'a> {
+    pub key: String,
+    pub citation: &'a Citation,
+}
+
+/// Every citation of `references`, in order, with keys that are unique
+/// among them.
+pub fn entries(references: &[Reference]) -> Vec<Entry<'_>> {
+    let mut entries: Vec<Entry> = vec![];
+    for reference in references {
+        for citation in reference.citations.iter() {
+            let base = citation
+                .id
+                .as_deref()
+                .map(|id| {
+                    id.trim_start_matches("Reference-")
+                        .trim_start_matches("CITEREF")
+                })
+                .map(key_chars)
+                .filter(|key| !key.is_empty())
+                .unwrap_or_else(|| key_chars(&reference.id));
+
+            // A second citation under the same key gets `b`, then `c`...
+            let mut key = base.clone();
+            let mut suffix = b'b';
+            while entries.iter().any(|entry| entry.key == key) {
+                key = format!("{}{}", base, suffix as char);
+                suffix += 1;
+            }
+            entries.push(Entry { key, citation });
+        }
+    }
+    entries
+}
+
+/// The characters of `s` BibTeX allows in a key.
+fn key_chars(s: &str) -> String {
+    s.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+        .collect()
+}
+
+/// The CSL item type for a `Citation::kind`.
+fn csl_type(kind: Option<&str>) -> &'static str {
+    match kind {
+        Some("book") => "book",
+        Some("journal") => "article-journal",
+        Some("news") => "article-newspaper",
+        Some("web") => "webpage",
+        Some("thesis") => "thesis",
+        _ => "document",
+    }
+}
+
+/// The BibTeX entry type for a `Citation::kind`.
+fn bibtex_type(kind: Option<&str>) -> &'static str {
+    match kind {
+        Some("book") => "book",
+        Some("journal") | Some("news") => "article",
+        Some("thesis") => "phdthesis",
+        _ => "misc",
+    }
+}
+
+/// `p. 221` or `pp. 10–12` without the abbreviation.
+fn page_numbers(pages: &str) -> &str {
+    pages
+        .trim_start_matches("pp.")
+        .trim_start_matches("p.")
+        .trim()
+}
+
+/// The year, month and day of an ISO-style date, as far as they go.
+fn date_parts(date: &str) -> Option<Vec<u32>> {
+    let parts = date
+        .split('-')
+        .take(3)
+        .map(|part| part.trim().parse::<u32>().ok())
+        .collect::<Option<Vec<u32>>>()?;
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts)
+    }
+}
+
+/// A CSL-JSON item.
+pub fn csl_item(entry: &Entry) -> Value {
+    let citation = entry.citation;
+    let mut item = Map::new();
+    item.insert("id".to_string(), json!(entry.key));
+    item.insert(
+        "type".to_string(),
+        json!(csl_type(citation.kind.as_deref())),
+    );
+
+    if !citation.authors.is_empty() {
+        let authors: Vec<Value> = citation
+            .authors
+            .iter()
+            .map(|name| {
+                let mut parts = name.splitn(2, ", ");
+                match (parts.next(), parts.next()) {
+                    (Some(family), Some(given)) => json!({ "family": family, "given": given }),
+                    _ => json!({ "literal": name }),
+                }
+            })
+            .collect();
+        item.insert("author".to_string(), Value::Array(authors));
+    }
+
+    let mut string = |name: &str, value: Option<&str>| {
+        if let Some(value) = value {
+            item.insert(name.to_string(), json!(value));
+        }
+    };
+    string("title", citation.title.as_deref());
+    string("container-title", citation.container.as_deref());
+    string("publisher", citation.publisher.as_deref());
+    string("page", citation.pages.as_deref().map(page_numbers));
+    string("ISBN", citation.isbn.as_deref());
+    string("URL", citation.url.as_deref());
+    if citation.title.is_none() {
+        string("note", Some(&citation.text));
+    }
+
+    if let Some(ref date) = citation.date {
+        let issued = match date_parts(date) {
+            Some(parts) => json!({ "date-parts": [parts] }),
+            None => json!({ "raw": date }),
+        };
+        item.insert("issued".to_string(), issued);
+    }
+
+    Value::Object(item)
+}
+
+/// Escapes the characters BibTeX and LaTeX treat specially.
+pub fn escape_bibtex(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '{' | '}' | '%' | '&' | '$' | '#' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A BibTeX entry, such as `@book{key,\n  title = {...},\n}`.
+pub fn bibtex_entry(entry: &Entry) -> String {
+    let citation = entry.citation;
+    let mut fields: Vec<(&str, String)> = vec![];
+
+    if !citation.authors.is_empty() {
+        let authors: Vec<String> = citation
+            .authors
+            .iter()
+            .map(|name| {
+                // A name with no comma is a single unit, such as a Japanese name.
+                if name.contains(',') {
+                    escape_bibtex(name)
+                } else {
+                    format!("{{{}}}", escape_bibtex(name))
+                }
+            })
+            .collect();
+        fields.push(("author", authors.join(" and ")));
+    }
+    if let Some(ref title) = citation.title {
+        fields.push(("title", escape_bibtex(title)));
+    }
+    if let Some(ref container) = citation.container {
+        fields.push(("journal", escape_bibtex(container)));
+    }
+    if let Some(ref publisher) = citation.publisher {
+        fields.push(("publisher", escape_bibtex(publisher)));
+    }
+    if let Some(ref date) = citation.date {
+        match date_parts(date) {
+            Some(parts) => {
+                fields.push(("year", parts[0].to_string()));
+                if let Some(month) = parts.get(1) {
+                    fields.push(("month", month.to_string()));
+                }
+            }
+            None => fields.push(("year", escape_bibtex(date))),
+        }
+    }
+    if let Some(ref pages) = citation.pages {
+        fields.push(("pages", escape_bibtex(page_numbers(pages))));
+    }
+    if let Some(ref isbn) = citation.isbn {
+        fields.push(("isbn", escape_bibtex(isbn)));
+    }
+    if let Some(ref url) = citation.url {
+        // `url` fields are read verbatim by the url package.
+        fields.push(("url", url.clone()));
+    }
+    if citation.title.is_none() {
+        fields.push(("note", escape_bibtex(&citation.text)));
+    }
+
+    let mut s = format!(
+        "@{}{{{},\n",
+        bibtex_type(citation.kind.as_deref()),
+        entry.key
+    );
+    for (name, value) in fields {
+        s.push_str(&format!("  {} = {{{}}},\n", name, value));
+    }
+    s.push_str("}\n");
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(id: &str, citations: Vec<Citation>) -> Reference {
+        Reference {
+            id: id.to_string(),
+            marker: String::new(),
+            text: String::new(),
+            citations,
+            back_links: vec![],
+        }
+    }
+
+    #[test]
+    fn export() {
+        let book = Citation {
+            kind: Some("book".to_string()),
+            text: "ボイス・ペンローズ 荒尾克己訳 (1985). 大航海時代. 筑摩書房. p. 221".to_string(),
+            title: Some("大航海時代".to_string()),
+            authors: vec!["ボイス・ペンローズ".to_string()],
+            date: Some("1985".to_string()),
+            publisher: Some("筑摩書房".to_string()),
+            pages: Some("p. 221".to_string()),
+            ..Citation::default()
+        };
+        let web = Citation {
+            id: Some("CITEREFDrake_&_Co".to_string()),
+            kind: Some("web".to_string()),
+            text: "Drake, Francis. \"The World Encompassed\"".to_string(),
+            title: Some("The World Encompassed & 100%".to_string()),
+            authors: vec!["Drake, Francis".to_string()],
+            date: Some("2019-05-02".to_string()),
+            url: Some("https://example.org/drake_1.html".to_string()),
+            ..Citation::default()
+        };
+        let untitled = Citation {
+            id: Some("Reference-Maxine_Snowden-2016".to_string()),
+            kind: Some("book".to_string()),
+            text: "Maxine Snowden『北極・南極探検の歴史』丸善出版、2016年".to_string(),
+            date: Some("2016".to_string()),
+            isbn: Some("978-4-621-30068-8".to_string()),
+            ..Citation::default()
+        };
+        let references = vec![
+            reference("cite_note-2", vec![book.clone()]),
+            reference("cite_note-3", vec![web, untitled]),
+            reference("cite_note-2", vec![book]),
+            reference("cite_note-4", vec![]),
+        ];
+
+        let entries = entries(&references);
+        let keys: Vec<&str> = entries.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                "cite_note-2",
+                "Drake__Co",
+                "Maxine_Snowden-2016",
+                "cite_note-2b"
+            ]
+        );
+
+        assert_eq!(
+            csl_item(&entries[0]),
+            json!({
+                "id": "cite_note-2",
+                "type": "book",
+                "author": [{ "literal": "ボイス・ペンローズ" }],
+                "title": "大航海時代",
+                "publisher": "筑摩書房",
+                "page": "221",
+                "issued": { "date-parts": [[1985]] },
+            })
+        );
+        let web = csl_item(&entries[1]);
+        assert_eq!(web["type"], "webpage");
+        assert_eq!(web["author"][0]["family"], "Drake");
+        assert_eq!(web["issued"]["date-parts"], json!([[2019, 5, 2]]));
+        assert_eq!(
+            csl_item(&entries[2])["note"],
+            "Maxine Snowden『北極・南極探検の歴史』丸善出版、2016年"
+        );
+
+        assert_eq!(
+            bibtex_entry(&entries[0]),
+            "@book{cite_note-2,\n  author = {{ボイス・ペンローズ}},\n  title = {大航海時代},\n  \
+             publisher = {筑摩書房},\n  year = {1985},\n  pages = {221},\n}\n"
+        );
+        assert_eq!(
+            bibtex_entry(&entries[1]),
+            "@misc{Drake__Co,\n  author = {Drake, Francis},\n  \
+             title = {The World Encompassed \\& 100\\%},\n  year = {2019},\n  month = {5},\n  \
+             url = {https://example.org/drake_1.html},\n}\n"
+        );
+        assert!(
+            bibtex_entry(&entries[2]).contains("  isbn = {978-4-621-30068-8},\n  note = {Maxine")
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! An on-disk cache of HTTP responses, keyed by URL.
+//!
+//! Each entry is two files named after a hash of the URL: `<key>.json` with
+//! the status and headers, and `<key>.body` with the raw response body.
+
+use crate::charset::{self, DecodeError};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How a cached fetch may use the network.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CachePolicy {
+    /// Serve cached entries after revalidating them with the server.
+    Revalidate,
+    /// Serve only what is already cached and never touch the network.
+    Offline,
+}
+
+/// A stored response.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub url: String,
+    pub status: u16,
+    /// Header names are lowercase.
+    pub headers: Vec<(String, String)>,
+    #[serde(skip)]
+    pub body: Vec<u8>,
+}
+
+impl CachedResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn etag(&self) -> Option<&str> {
+        self.header("etag")
+    }
+
+    pub fn last_modified(&self) -> Option<&str> {
+        self.header("last-modified")
+    }
+
+    /// Decodes the body in the encoding its `Content-Type` or content
+    /// declares; see `charset::decode`.
+    pub fn decode(&self) -> Result<String, DecodeError> {
+        charset::decode(&self.body, self.header("content-type"))
+    }
+
+    /// Replaces or adds the headers sent with a `304 Not Modified`.
+    pub fn merge_headers(&mut self, headers: Vec<(String, String)>) {
+        for (name, value) in headers {
+            match self.headers.iter_mut().find(|(k, _)| *k == name) {
+                Some(header) => header.1 = value,
+                None => self.headers.push((name, value)),
+            }
+        }
+    }
+}
+
+/// A directory of cached responses.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// Opens the cache in `dir`, creating the directory if needed.
+    pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Cache {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The file stem used for `url`: a 64-bit FNV-1a hash in hex.
+    pub fn key(url: &str) -> String {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for b in url.bytes() {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        format!("{:016x}", hash)
+    }
+
+    fn paths(&self, url: &str) -> (PathBuf, PathBuf) {
+        let key = Cache::key(url);
+        (
+            self.dir.join(format!("{}.json", key)),
+            self.dir.join(format!("{}.body", key)),
+        )
+    }
+
+    pub fn get(&self, url: &str) -> io::Result<Option<CachedResponse>> {
+        let (meta_path, body_path) = self.paths(url);
+
+        let meta = match fs::read(&meta_path) {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let mut response: CachedResponse = serde_json::from_slice(&meta)?;
+        if response.url != url {
+            // A hash collision; treat it as a miss.
+            return Ok(None);
+        }
+
+        response.body = fs::read(&body_path)?;
+
+        Ok(Some(response))
+    }
+
+    pub fn put(&self, response: &CachedResponse) -> io::Result<()> {
+        let (meta_path, body_path) = self.paths(&response.url);
+
+        // The body goes first so that a readable `.json` always has a complete body.
+        write_atomic(&body_path, &response.body)?;
+        write_atomic(&meta_path, &serde_json::to_vec_pretty(response)?)
+    }
+}
+
+/// Writes `data` to a temporary file next to `path` and renames it into place.
+///
+/// The temporary name keeps the full file name and adds the process id and a
+/// counter, so concurrent writers of the same entry never share one.
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp = path.with_file_name(name);
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)
+}
+
+/// Fetches `url` through `cache` according to `policy`.
+///
+/// Cached entries are revalidated with `If-None-Match`/`If-Modified-Since`
+/// and served as-is on `304 Not Modified`.
+#[cfg(feature = "fetch")]
+pub async fn fetch(
+    client: &reqwest::Client,
+    cache: &Cache,
+    url: &str,
+    policy: CachePolicy,
+) -> Result<CachedResponse, Box<dyn std::error::Error>> {
+    use reqwest::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH};
+    use reqwest::StatusCode;
+
+    let cached = cache.get(url)?;
+
+    if policy == CachePolicy::Offline {
+        return cached.ok_or_else(|| format!("{} is not in the cache", url).into());
+    }
+
+    let mut request = client.get(url);
+    if let Some(cached) = &cached {
+        if let Some(etag) = cached.etag() {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = cached.last_modified() {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let resp = request.send().await?;
+    let headers: Vec<(String, String)> = resp
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| {
+            value
+                .to_str()
+                .ok()
+                .map(|value| (name.as_str().to_string(), value.to_string()))
+        })
+        .collect();
+
+    if resp.status() == StatusCode::NOT_MODIFIED {
+        if let Some(mut cached) = cached {
+            cached.merge_headers(headers);
+            cache.put(&cached)?;
+            return Ok(cached);
+        }
+    }
+
+    let resp = resp.error_for_status()?;
+    let response = CachedResponse {
+        url: url.to_string(),
+        status: resp.status().as_u16(),
+        headers,
+        body: resp.bytes().await?.to_vec(),
+    };
+    cache.put(&response)?;
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn put_and_get() {
+        let dir = std::env::temp_dir().join(format!("francis_wb_cache_{}", std::process::id()));
+        let cache = Cache::new(&dir).unwrap();
+        let url = "https://ja.wikipedia.org/wiki/A";
+
+        assert_eq!(cache.get(url).unwrap(), None);
+
+        let mut response = CachedResponse {
+            url: url.to_string(),
+            status: 200,
+            headers: vec![("etag".to_string(), "\"1\"".to_string())],
+            body: "<p>あ</p>".as_bytes().to_vec(),
+        };
+        cache.put(&response).unwrap();
+        assert_eq!(cache.get(url).unwrap(), Some(response.clone()));
+        assert_eq!(cache.get("https://ja.wikipedia.org/wiki/B").unwrap(), None);
+
+        response.merge_headers(vec![
+            ("etag".to_string(), "\"2\"".to_string()),
+            ("date".to_string(), "x".to_string()),
+        ]);
+        assert_eq!(response.etag(), Some("\"2\""));
+        assert_eq!(response.headers.len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_puts() {
+        let dir = std::env::temp_dir().join(format!(
+            "francis_wb_cache_concurrent_{}",
+            std::process::id()
+        ));
+        let cache = std::sync::Arc::new(Cache::new(&dir).unwrap());
+        let url = "https://ja.wikipedia.org/wiki/A";
+
+        let writers: Vec<_> = (0..8u8)
+            .map(|i| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..20 {
+                        cache
+                            .put(&CachedResponse {
+                                url: url.to_string(),
+                                status: 200,
+                                headers: vec![],
+                                body: vec![i; 4096],
+                            })
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        // Whichever writer won, the body is one writer's complete body.
+        let body = cache.get(url).unwrap().unwrap().body;
+        assert_eq!(body.len(), 4096);
+        assert!(body.iter().all(|&b| b == body[0]));
+
+        let leftovers: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .filter(|name| name.to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty(), "{:?}", leftovers);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
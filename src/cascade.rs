@@ -0,0 +1,668 @@
+//! Style sheets and the cascade: the computed style of every element.
+//!
+//! A [`Cascade`] holds the rules of the built-in default sheet
+//! ([`USER_AGENT_CSS`]), of the external sheets it is given and of the
+//! `<style>` elements of a document. For each element, the declarations of
+//! the rules whose selectors match it and of its `style` attribute are
+//! ordered by origin and importance (default styles, then the page's, then
+//! the page's `!important` ones, then the default `!important` ones), then
+//! by whether they are inline, then by specificity, then by source order;
+//! the last one of each property wins.
+//! Box shorthands such as `padding` are expanded into their longhands first.
+//! Inherited properties (fonts, `white-space`, `text-align`...) that an
+//! element does not set come from its parent.
+//!
+//! Rules are kept if their whole selector list parses with
+//! [`Selector::parse`], which leaves out pseudo-elements and dynamic
+//! pseudo-classes such as `:hover`. Of the at-rules, only `@media screen`
+//! and `@media all` blocks are read; `@import` and the rest are skipped.
+
+use crate::dom::get_attr;
+use crate::select::{Complex, Selector, Simple, Specificity};
+use crate::style::{expand_shorthand, parse_declarations, Declaration, Length, LengthContext};
+use crate::traverse::{self, local_name};
+use crate::whitespace::WhiteSpace;
+use cssparser::{Delimiter, ParseError, Parser, ParserInput, Token};
+use html5ever::rcdom::{Handle, Node, NodeData};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::rc::Rc;
+
+/// The properties an element takes from its parent when it does not set them.
+pub const INHERITED: &[&str] = &[
+    "color",
+    "direction",
+    "font-family",
+    "font-size",
+    "font-style",
+    "font-variant",
+    "font-weight",
+    "letter-spacing",
+    "line-height",
+    "list-style-type",
+    "list-style-position",
+    "quotes",
+    "text-align",
+    "text-indent",
+    "text-transform",
+    "visibility",
+    "white-space",
+    "word-break",
+    "word-spacing",
+];
+
+/// Default styles for HTML elements, such as `th { font-weight: bold }`
+/// and `script { display: none }`.
+pub const USER_AGENT_CSS: &str = include_str!("html.css");
+
+/// The font size of the root element unless a sheet sets one, in pixels.
+pub const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+/// One style rule: a selector list and its declarations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub selector: Selector,
+    pub declarations: Vec<Declaration>,
+}
+
+/// The rules of one style sheet, in source order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stylesheet {
+    pub rules: Vec<Rule>,
+}
+
+impl Stylesheet {
+    /// Reads the style rules of `css`, skipping what it does not understand.
+    pub fn parse(css: &str) -> Self {
+        let mut input = ParserInput::new(css);
+        let mut parser = Parser::new(&mut input);
+        let mut rules = vec![];
+        parse_rules(&mut parser, &mut rules);
+        Stylesheet { rules }
+    }
+
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Stylesheet::parse(&fs::read_to_string(path)?))
+    }
+
+    /// The rules of [`USER_AGENT_CSS`].
+    pub fn user_agent() -> Self {
+        Stylesheet::parse(USER_AGENT_CSS)
+    }
+}
+
+type Ignored<'i> = ParseError<'i, ()>;
+
+/// Reads rules up to the end of `parser`, descending into `@media` blocks
+/// that apply.
+fn parse_rules<'i, 't>(parser: &mut Parser<'i, 't>, rules: &mut Vec<Rule>) {
+    loop {
+        let start = parser.position();
+        let token = match parser.next() {
+            Ok(token) => token.clone(),
+            Err(_) => break,
+        };
+
+        match token {
+            Token::CDO | Token::CDC => {}
+            Token::AtKeyword(ref name) => {
+                let prelude_start = parser.position();
+                let _ = parser.parse_until_before(
+                    Delimiter::Semicolon | Delimiter::CurlyBracketBlock,
+                    |parser| {
+                        while parser.next().is_ok() {}
+                        Ok::<_, Ignored>(())
+                    },
+                );
+                let prelude = parser.slice_from(prelude_start).to_string();
+                // A statement ends at the `;`; a block is skipped unless read here.
+                if let Ok(Token::CurlyBracketBlock) = parser.next() {
+                    if name.eq_ignore_ascii_case("media") && media_applies(&prelude) {
+                        let _ = parser.parse_nested_block(|parser| {
+                            parse_rules(parser, rules);
+                            Ok::<_, Ignored>(())
+                        });
+                    }
+                }
+            }
+            _ => {
+                let _ = parser.parse_until_before(Delimiter::CurlyBracketBlock, |parser| {
+                    while parser.next().is_ok() {}
+                    Ok::<_, Ignored>(())
+                });
+                let prelude = parser.slice_from(start).to_string();
+                if let Ok(Token::CurlyBracketBlock) = parser.next() {
+                    let block = parser.parse_nested_block(|parser| {
+                        let start = parser.position();
+                        while parser.next().is_ok() {}
+                        Ok::<_, Ignored>(parser.slice_from(start).to_string())
+                    });
+                    if let (Ok(selector), Ok(block)) = (Selector::parse(prelude.trim()), block) {
+                        rules.push(Rule {
+                            selector,
+                            declarations: parse_declarations(&block),
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Whether a media query list matches a screen of unknown size.
+fn media_applies(queries: &str) -> bool {
+    queries.split(',').any(|query| {
+        matches!(
+            query.trim().to_ascii_lowercase().as_str(),
+            "" | "all" | "screen" | "only screen"
+        )
+    })
+}
+
+/// Where the rules of a style sheet come from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Origin {
+    /// The browser's defaults, such as [`USER_AGENT_CSS`].
+    UserAgent,
+    /// The page: its `<style>` elements, the sheets it links to and its
+    /// `style` attributes.
+    Author,
+}
+
+impl Origin {
+    /// How origin and importance rank in the cascade; greater wins.
+    fn rank(self, important: bool) -> u8 {
+        match (self, important) {
+            (Origin::UserAgent, false) => 0,
+            (Origin::Author, false) => 1,
+            (Origin::Author, true) => 2,
+            (Origin::UserAgent, true) => 3,
+        }
+    }
+}
+
+/// Where a matched declaration stands in the cascade; greater wins.
+type Precedence = (u8, bool, Specificity, usize);
+
+/// One complex selector of a rule, indexed by what its last compound requires.
+struct IndexedRule {
+    origin: Origin,
+    selector: Complex,
+    specificity: Specificity,
+    order: usize,
+    declarations: Rc<Vec<Declaration>>,
+}
+
+/// Rules ready to be matched against a document.
+#[derive(Default)]
+pub struct Cascade {
+    // Each rule is filed under one id, class or tag its subject needs,
+    // so only a few are tried against each element.
+    by_id: HashMap<String, Vec<IndexedRule>>,
+    by_class: HashMap<String, Vec<IndexedRule>>,
+    by_tag: HashMap<String, Vec<IndexedRule>>,
+    universal: Vec<IndexedRule>,
+    next_order: usize,
+}
+
+impl Cascade {
+    pub fn new() -> Self {
+        Cascade::default()
+    }
+
+    /// A cascade that starts with the rules of [`USER_AGENT_CSS`].
+    pub fn with_user_agent() -> Self {
+        let mut cascade = Cascade::new();
+        cascade.add_origin(&Stylesheet::user_agent(), Origin::UserAgent);
+        cascade
+    }
+
+    /// Adds the rules of the author sheet `sheet` after the ones already added.
+    pub fn add(&mut self, sheet: &Stylesheet) {
+        self.add_origin(sheet, Origin::Author);
+    }
+
+    /// Adds the rules of `sheet`, from `origin`, after the ones already added.
+    pub fn add_origin(&mut self, sheet: &Stylesheet, origin: Origin) {
+        for rule in sheet.rules.iter() {
+            let declarations = Rc::new(rule.declarations.clone());
+            for complex in rule.selector.alternatives.iter() {
+                let indexed = IndexedRule {
+                    origin,
+                    selector: complex.clone(),
+                    specificity: complex.specificity(),
+                    order: self.next_order,
+                    declarations: declarations.clone(),
+                };
+                self.next_order += 1;
+
+                let subject = match complex.parts.last() {
+                    Some((_, compound)) => compound,
+                    None => continue,
+                };
+                let id = subject.conditions.iter().find_map(|c| match c {
+                    Simple::Id(id) => Some(id),
+                    _ => None,
+                });
+                let class = subject.conditions.iter().find_map(|c| match c {
+                    Simple::Class(class) => Some(class),
+                    _ => None,
+                });
+                let bucket = match (id, class, &subject.tag) {
+                    (Some(id), _, _) => self.by_id.entry(id.clone()).or_default(),
+                    (_, Some(class), _) => self.by_class.entry(class.clone()).or_default(),
+                    (_, _, Some(tag)) => self.by_tag.entry(tag.clone()).or_default(),
+                    _ => &mut self.universal,
+                };
+                bucket.push(indexed);
+            }
+        }
+    }
+
+    /// Adds the `<style>` elements under `root`, in document order.
+    /// `media` other than `screen` or `all` leaves a sheet out.
+    pub fn add_style_elements(&mut self, root: &Handle) {
+        let style = html5ever::LocalName::from("style");
+        for node in std::iter::once(root.clone()).chain(traverse::descendants(root)) {
+            if !traverse::is_element(&node, &style) {
+                continue;
+            }
+            if get_attr(&node, "media").is_some_and(|media| !media_applies(&media)) {
+                continue;
+            }
+            let css: String = traverse::text_nodes(&node)
+                .map(|text| match text.data {
+                    NodeData::Text { ref contents } => contents.borrow().to_string(),
+                    _ => String::new(),
+                })
+                .collect();
+            self.add(&Stylesheet::parse(&css));
+        }
+    }
+
+    /// The declarations that apply to `node`, in the order they are applied.
+    fn declarations(&self, node: &Handle) -> Vec<Declaration> {
+        let inline = get_attr(node, "style")
+            .map(|css| parse_declarations(&css))
+            .unwrap_or_default();
+        let mut matched: Vec<(Precedence, &Declaration)> = vec![];
+
+        if let Some(id) = get_attr(node, "id") {
+            match_rules(self.by_id.get(&id), node, &mut matched);
+        }
+        if let Some(classes) = get_attr(node, "class") {
+            let mut seen: Vec<&str> = vec![];
+            for class in classes.split_whitespace() {
+                if !seen.contains(&class) {
+                    seen.push(class);
+                    match_rules(self.by_class.get(class), node, &mut matched);
+                }
+            }
+        }
+        if let Some(name) = local_name(node) {
+            match_rules(self.by_tag.get(&**name), node, &mut matched);
+        }
+        match_rules(Some(&self.universal), node, &mut matched);
+
+        for (order, declaration) in inline.iter().enumerate() {
+            let rank = Origin::Author.rank(declaration.important);
+            matched.push(((rank, true, (0, 0, 0), order), declaration));
+        }
+
+        matched.sort_by_key(|(precedence, _)| *precedence);
+        matched
+            .into_iter()
+            .map(|(_, declaration)| declaration.clone())
+            .collect()
+    }
+
+    /// The style of the element `node` whose parent's style is `parent`.
+    pub fn style_of(&self, node: &Handle, parent: &ComputedStyle) -> ComputedStyle {
+        ComputedStyle::cascade(parent, &self.declarations(node))
+    }
+
+    /// The computed style of every element under `root`, which is usually
+    /// the document node.
+    pub fn compute(&self, root: &Handle) -> ComputedStyles {
+        let mut styles = ComputedStyles::default();
+        self.compute_children(root, &ComputedStyle::default(), &mut styles);
+        styles
+    }
+
+    fn compute_children(&self, node: &Handle, parent: &ComputedStyle, styles: &mut ComputedStyles) {
+        let is_document = matches!(node.data, NodeData::Document);
+        for child in node.children.borrow().iter() {
+            if local_name(child).is_none() {
+                continue;
+            }
+            let mut style = self.style_of(child, parent);
+            if is_document {
+                style.root_font_size = style.font_size;
+            }
+            self.compute_children(child, &style, styles);
+            styles.insert(child, style);
+        }
+    }
+}
+
+/// Adds the declarations of the rules that match `node`.
+fn match_rules<'a>(
+    rules: Option<&'a Vec<IndexedRule>>,
+    node: &Handle,
+    matched: &mut Vec<(Precedence, &'a Declaration)>,
+) {
+    for rule in rules.into_iter().flatten() {
+        if rule.selector.matches(node) {
+            for declaration in rule.declarations.iter() {
+                let rank = rule.origin.rank(declaration.important);
+                let precedence = (rank, false, rule.specificity, rule.order);
+                matched.push((precedence, declaration));
+            }
+        }
+    }
+}
+
+/// The properties of one element after the cascade and inheritance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ComputedStyle {
+    // Lowercased names; `font-size` is always in pixels.
+    properties: BTreeMap<String, String>,
+    /// In pixels.
+    pub font_size: f32,
+    /// The font size of the root element, for `rem`.
+    pub root_font_size: f32,
+    /// Whether the element or one of its ancestors is `display: none`, so
+    /// that nothing of it is drawn.
+    pub hidden: bool,
+}
+
+impl Default for ComputedStyle {
+    fn default() -> Self {
+        ComputedStyle {
+            properties: BTreeMap::new(),
+            font_size: DEFAULT_FONT_SIZE,
+            root_font_size: DEFAULT_FONT_SIZE,
+            hidden: false,
+        }
+    }
+}
+
+impl ComputedStyle {
+    /// The style of an element with `parent`'s style whose declarations,
+    /// lowest precedence first, are `declarations`.
+    pub fn cascade(parent: &ComputedStyle, declarations: &[Declaration]) -> Self {
+        let mut properties: BTreeMap<String, String> = parent
+            .properties
+            .iter()
+            .filter(|(name, _)| INHERITED.contains(&name.as_str()))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+
+        for declaration in declarations.iter().flat_map(expand_shorthand) {
+            let name = &declaration.name;
+            let inherited = INHERITED.contains(&name.as_str());
+            match declaration.value.to_ascii_lowercase().as_str() {
+                "inherit" => match parent.properties.get(name) {
+                    Some(value) => properties.insert(name.clone(), value.clone()),
+                    None => properties.remove(name),
+                },
+                "unset" if inherited => match parent.properties.get(name) {
+                    Some(value) => properties.insert(name.clone(), value.clone()),
+                    None => properties.remove(name),
+                },
+                "initial" | "unset" => properties.remove(name),
+                _ => properties.insert(name.clone(), declaration.value.clone()),
+            };
+        }
+
+        let font_size = match properties.get("font-size") {
+            Some(value) => font_size(value, parent).unwrap_or(parent.font_size),
+            None => parent.font_size,
+        };
+        if properties.contains_key("font-size") {
+            properties.insert("font-size".to_string(), format!("{}px", font_size));
+        }
+
+        let hidden = parent.hidden
+            || properties
+                .get("display")
+                .is_some_and(|display| display.eq_ignore_ascii_case("none"));
+
+        ComputedStyle {
+            properties,
+            font_size,
+            root_font_size: parent.root_font_size,
+            hidden,
+        }
+    }
+
+    /// The value of a property, as written; `font-size` is in pixels.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.properties.get(name).map(|value| value.as_str())
+    }
+
+    /// The `display` value, `inline` unless set.
+    pub fn display(&self) -> &str {
+        self.get("display").unwrap_or("inline")
+    }
+
+    /// The `white-space` value, `normal` unless set.
+    pub fn white_space(&self) -> WhiteSpace {
+        self.get("white-space")
+            .and_then(WhiteSpace::from_css)
+            .unwrap_or(WhiteSpace::Normal)
+    }
+
+    /// Every property that is set, by name.
+    pub fn properties(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.properties
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// What `em` and `rem` lengths of this element are relative to.
+    pub fn length_context(&self) -> LengthContext {
+        LengthContext {
+            font_size: self.font_size,
+            root_font_size: self.root_font_size,
+            ..LengthContext::default()
+        }
+    }
+}
+
+/// A `font-size` value in pixels, relative to the parent's size.
+fn font_size(value: &str, parent: &ComputedStyle) -> Option<f32> {
+    let keyword = match value.trim().to_ascii_lowercase().as_str() {
+        "xx-small" => Some(9.0),
+        "x-small" => Some(10.0),
+        "small" => Some(13.0),
+        "medium" => Some(16.0),
+        "large" => Some(18.0),
+        "x-large" => Some(24.0),
+        "xx-large" => Some(32.0),
+        "smaller" => Some(parent.font_size / 1.2),
+        "larger" => Some(parent.font_size * 1.2),
+        _ => None,
+    };
+    if keyword.is_some() {
+        return keyword;
+    }
+
+    let ctx = LengthContext {
+        font_size: parent.font_size,
+        root_font_size: parent.root_font_size,
+        ..LengthContext::default()
+    };
+    Length::parse(value)?
+        .resolve(&ctx, Some(parent.font_size))
+        .filter(|px| *px >= 0.0)
+}
+
+/// The computed styles of the elements of one document.
+#[derive(Default)]
+pub struct ComputedStyles {
+    // Keyed by node address; the handle keeps the node (and the address) alive.
+    styles: HashMap<*const Node, (Handle, ComputedStyle)>,
+}
+
+impl ComputedStyles {
+    /// The style of `node`, if it is an element that was computed.
+    pub fn get(&self, node: &Handle) -> Option<&ComputedStyle> {
+        self.styles
+            .get(&(&**node as *const Node))
+            .map(|(_, style)| style)
+    }
+
+    fn insert(&mut self, node: &Handle, style: ComputedStyle) {
+        self.styles
+            .insert(&**node as *const Node, (node.clone(), style));
+    }
+
+    pub fn len(&self) -> usize {
+        self.styles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.styles.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::parse;
+    use crate::select::select;
+    use crate::style::BlockProps;
+    use crate::Pipeline;
+
+    const HTML: &str = r#"<html><head><style>
+        table { width: 10px; font-size: 12px }
+        .infobox { width: 22em !important }
+        #drake.infobox { width: 300px }
+        @media print { th { color: black } }
+        @media screen { th { color: navy } }
+        p:hover, th { font-weight: bold }
+        th { color: maroon; line-height: 1.5; border-width: 1px }
+    </style></head><body style="font-size: 20px; white-space: nowrap">
+        <table id="drake" class="infobox" style="height: 2em; width: 5px">
+            <tr><th style="font-size: 150%; color: inherit">生誕</th><td>1543年頃</td></tr>
+        </table>
+        <table style="font-size: smaller"><tr><td style="font-size: 2rem">a</td></tr></table>
+    </body></html>"#;
+
+    #[test]
+    fn stylesheet() {
+        let sheet = Stylesheet::parse(
+            "<!-- a { color: red } --> @import url(x.css); @font-face { src: url(x) }
+             @media print { b { color: red } }
+             @media screen, print { i { color: red } }
+             a::before, b { color: red } .x > .y, td { width: 1px; width: 2px !important }",
+        );
+        let selectors: Vec<usize> = sheet
+            .rules
+            .iter()
+            .map(|rule| rule.selector.alternatives.len())
+            .collect();
+        assert_eq!(selectors, [1, 1, 2]);
+        assert_eq!(
+            sheet.rules[2].declarations,
+            [
+                Declaration {
+                    name: "width".to_string(),
+                    value: "1px".to_string(),
+                    important: false
+                },
+                Declaration {
+                    name: "width".to_string(),
+                    value: "2px".to_string(),
+                    important: true
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn cascade() {
+        let dom = parse(HTML);
+        let mut cascade = Cascade::new();
+        cascade.add(&Stylesheet::parse(
+            "table { width: 90% !important; margin: 0 }",
+        ));
+        cascade.add_style_elements(&dom.document);
+        let styles = cascade.compute(&dom.document);
+
+        let style = |selector: &str| styles.get(&select(&dom.document, selector).unwrap()[0]);
+        let table = style("#drake").unwrap();
+        // The more specific `!important` wins, whatever the sheet.
+        assert_eq!(table.get("width"), Some("22em"));
+        assert_eq!(table.get("height"), Some("2em"));
+        assert_eq!(table.get("margin"), None);
+        assert_eq!(table.get("margin-left"), Some("0"));
+        assert_eq!(table.get("white-space"), Some("nowrap"));
+        assert_eq!(table.font_size, 12.0);
+        let props = BlockProps::from_computed(table).resolve(&table.length_context());
+        assert_eq!((props.width, props.height), (Some(264), Some(24)));
+
+        let th = style("th").unwrap();
+        assert_eq!(th.font_size, 18.0);
+        assert_eq!(th.get("font-size"), Some("18px"));
+        assert_eq!(th.get("color"), None);
+        assert_eq!(th.get("font-weight"), None);
+        assert_eq!(th.get("line-height"), Some("1.5"));
+        assert_eq!(style("td").unwrap().get("line-height"), None);
+        assert_eq!(style("td").unwrap().get("white-space"), Some("nowrap"));
+
+        let smaller = style("table + table").unwrap();
+        assert!((smaller.font_size - 20.0 / 1.2).abs() < 0.01);
+        assert_eq!(style("table + table td").unwrap().font_size, 32.0);
+        assert_eq!(styles.get(&dom.document), None);
+    }
+
+    #[test]
+    fn user_agent() {
+        let dom = parse(
+            "<head><script>x()</script></head><body><h2>航海</h2><ul><li>a</li></ul>\
+             <table hidden><tr><th>生誕</th><th style='text-align: left'>死没</th></tr></table>\
+             <p><b>b</b></p>",
+        );
+        let mut cascade = Cascade::with_user_agent();
+        cascade.add(&Stylesheet::parse(
+            "p { display: inline } li { padding-left: 0 }",
+        ));
+        cascade.add_origin(
+            &Stylesheet::parse("p { font-size: 10px !important } p { font-size: 20px }"),
+            Origin::UserAgent,
+        );
+        cascade.add(&Stylesheet::parse("body p { font-size: 30px !important }"));
+        let styles = cascade.compute(&dom.document);
+        let style = |selector: &str| styles.get(&select(&dom.document, selector).unwrap()[0]);
+
+        assert!(style("script").unwrap().hidden);
+        assert_eq!(style("h2").unwrap().font_size, 24.0);
+        assert_eq!(style("h2").unwrap().display(), "block");
+        assert_eq!(style("ul").unwrap().get("padding-left"), Some("40px"));
+        assert_eq!(style("li").unwrap().get("padding-left"), Some("0"));
+        assert_eq!(style("li").unwrap().get("list-style-type"), Some("disc"));
+        assert!(style("th").unwrap().hidden);
+        assert_eq!(style("th").unwrap().get("font-weight"), Some("bold"));
+        assert_eq!(style("th").unwrap().get("text-align"), Some("center"));
+        assert_eq!(style("th + th").unwrap().get("text-align"), Some("left"));
+        // The page beats the defaults, except for their `!important` rules.
+        assert_eq!(style("p").unwrap().display(), "inline");
+        assert_eq!(style("p").unwrap().font_size, 10.0);
+        assert_eq!(style("b").unwrap().get("font-weight"), Some("bolder"));
+
+        let html = "<table hidden><tr><td>a</td></tr></table><table><tr><td>b</td></tr></table>";
+        assert_eq!(Pipeline::new().parse(html).tables().len(), 1);
+        let without = Pipeline::new().user_agent_styles(false).parse(html);
+        assert_eq!(without.tables().len(), 2);
+        assert_eq!(
+            without
+                .computed_styles()
+                .get(&without.root().unwrap())
+                .unwrap()
+                .display(),
+            "inline"
+        );
+    }
+}
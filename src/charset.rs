@@ -0,0 +1,173 @@
+//! Working out the character encoding of an HTML document and decoding it.
+//!
+//! The encoding is taken from, in order: a byte order mark, the `charset`
+//! parameter of an HTTP `Content-Type`, a `<meta charset>` or
+//! `<meta http-equiv="Content-Type">` in the first 1024 bytes, and finally
+//! UTF-8.
+
+use encoding_rs::{Encoding, UTF_8};
+use std::fmt::{Display, Formatter};
+
+/// How many bytes are searched for a `<meta>` declaration, as in the HTML spec.
+const PRESCAN_LEN: usize = 1024;
+
+/// The bytes were not valid in the encoding they declared.
+#[derive(Debug, PartialEq)]
+pub struct DecodeError {
+    pub encoding: &'static str,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "the document is not valid {}", self.encoding)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Returns the encoding named by a byte order mark and the length of the mark.
+pub fn sniff_bom(bytes: &[u8]) -> Option<(&'static Encoding, usize)> {
+    Encoding::for_bom(bytes)
+}
+
+/// Reads the `charset` parameter of a `Content-Type` header value.
+pub fn charset_from_content_type(content_type: &str) -> Option<&'static Encoding> {
+    content_type
+        .split(';')
+        .skip(1)
+        .filter_map(|param| {
+            let mut kv = param.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some(k), Some(v)) if k.trim().eq_ignore_ascii_case("charset") => Some(v),
+                _ => None,
+            }
+        })
+        .next()
+        .and_then(|v| {
+            Encoding::for_label(v.trim().trim_matches(|c| c == '"' || c == '\'').as_bytes())
+        })
+}
+
+/// Looks for a `<meta>` charset declaration near the start of the document.
+pub fn sniff_meta(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = &bytes[..bytes.len().min(PRESCAN_LEN)];
+    let head = head.to_ascii_lowercase();
+
+    let mut pos = 0;
+    while let Some(i) = find(&head[pos..], b"<meta") {
+        let start = pos + i + b"<meta".len();
+        let end = find(&head[start..], b">").map_or(head.len(), |j| start + j);
+        let tag = &head[start..end];
+
+        if let Some(j) = find(tag, b"charset") {
+            let value = tag[j + b"charset".len()..]
+                .iter()
+                .skip_while(|&&b| b == b' ')
+                .skip_while(|&&b| b == b'=')
+                .skip_while(|&&b| b == b' ' || b == b'"' || b == b'\'')
+                .take_while(|&&b| !matches!(b, b'"' | b'\'' | b' ' | b';' | b'/' | b'>'))
+                .cloned()
+                .collect::<Vec<u8>>();
+
+            if let Some(encoding) = Encoding::for_label(&value) {
+                // A document that says UTF-16 in ASCII is not UTF-16.
+                return Some(encoding.output_encoding());
+            }
+        }
+
+        pos = end;
+    }
+
+    None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Picks the encoding of `bytes`; see the module documentation for the order.
+pub fn detect(bytes: &[u8], content_type: Option<&str>) -> &'static Encoding {
+    sniff_bom(bytes)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(charset_from_content_type))
+        .or_else(|| sniff_meta(bytes))
+        .unwrap_or(UTF_8)
+}
+
+/// Decodes an HTML document to a `String`.
+///
+/// Malformed input is an error rather than being replaced with U+FFFD.
+pub fn decode(bytes: &[u8], content_type: Option<&str>) -> Result<String, DecodeError> {
+    let encoding = detect(bytes, content_type);
+    let bytes = match sniff_bom(bytes) {
+        Some((_, bom_len)) => &bytes[bom_len..],
+        None => bytes,
+    };
+
+    encoding
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .map(|s| s.into_owned())
+        .ok_or(DecodeError {
+            encoding: encoding.name(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{EUC_JP, SHIFT_JIS, UTF_16LE};
+
+    const TEXT: &str = "<p>フランシス・ドレーク</p>";
+
+    #[test]
+    fn detect_encoding() {
+        let (sjis, _, _) = SHIFT_JIS.encode(TEXT);
+
+        let html = [
+            &b"<html><head><meta charset=\"Shift_JIS\"></head><body>"[..],
+            &sjis,
+        ]
+        .concat();
+        assert_eq!(detect(&html, None), SHIFT_JIS);
+        assert!(decode(&html, None).unwrap().ends_with(TEXT));
+
+        let html = [
+            &b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=euc-jp\">"[..],
+            &EUC_JP.encode(TEXT).0,
+        ]
+        .concat();
+        assert_eq!(detect(&html, None), EUC_JP);
+
+        // The header wins over the document; the BOM wins over both.
+        assert_eq!(
+            detect(&html, Some("text/html; charset=\"Shift_JIS\"")),
+            SHIFT_JIS
+        );
+        let bom = [&[0xEF, 0xBB, 0xBF][..], TEXT.as_bytes()].concat();
+        assert_eq!(detect(&bom, Some("text/html; charset=EUC-JP")), UTF_8);
+        assert_eq!(decode(&bom, None).unwrap(), TEXT);
+
+        assert_eq!(detect(b"<meta charset=utf-16le>", None), UTF_8);
+        assert_eq!(detect(&[0xFF, 0xFE, b'<', 0], None), UTF_16LE);
+        assert_eq!(detect(TEXT.as_bytes(), None), UTF_8);
+    }
+
+    #[test]
+    fn header_declared_encoding() {
+        // Nothing in the document says it is Shift_JIS.
+        let (sjis, _, _) = SHIFT_JIS.encode(TEXT);
+        assert_eq!(
+            decode(&sjis, Some("text/html; charset=Shift_JIS")).unwrap(),
+            TEXT
+        );
+        assert_eq!(charset_from_content_type("text/html"), None);
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        let (sjis, _, _) = SHIFT_JIS.encode(TEXT);
+        assert_eq!(decode(&sjis, None), Err(DecodeError { encoding: "UTF-8" }));
+    }
+}
@@ -0,0 +1,353 @@
+use francis_wb::export::Format;
+use francis_wb::mediawiki;
+use francis_wb::table::SpanFill;
+use std::fmt::{Display, Formatter};
+
+pub const USAGE: &str = "\
+usage: francis_wb <command> [options]
+
+commands:
+  fetch <title|url>   download an article to --output (default: francis_wiki.html)
+  dump-dom            print the cleaned DOM (formats: html, text)
+  tables              print every table (formats: text, json)
+  grid                print one table as rows with spans resolved (formats: csv, json, jsonl)
+  outline             print the section tree (formats: text, json)
+  infobox             print the infoboxes as key/value records (formats: json, text)
+  references          print the footnotes and their citations (formats: text, json)
+  citations           print the bibliography of the article (formats: bibtex, csl-json)
+  layout              print the geometry of every table (formats: text, json)
+  render              draw the table layout (formats: html, svg)
+  crawl <title>       fetch an article and the /wiki/ links around it into --output
+                      (default directory: pages)
+
+options:
+  -i, --input <path>    HTML file to read (default: francis_wiki.html)
+  -o, --output <path>   file to write (default: stdout)
+  -l, --lang <code>     article language for titles (default: ja)
+  -f, --format <name>   output format
+      --passes <path>   cleanup rules to use instead of the default ones
+      --css <path>      a style sheet to apply before the page's own `<style>`s;
+                        may be given more than once
+      --section <anchor>
+                        only use the section a URL fragment (`#...`) points to
+      --full-page       keep the skin, the TOC, navboxes and footnote markers instead
+                        of only the article with its references at the end
+  -v, --verbose         report what was removed from the page on stderr
+      --cache <dir>     keep fetched responses in <dir> and revalidate them
+      --offline         serve fetches from --cache only
+      --table <n>       grid: which table, counting from 0 (default: 0)
+      --fill <how>      grid: put a spanning cell's text in every slot it covers
+                        (`repeat`, the default) or only the first (`empty`)
+      --depth <n>       crawl: how many links away from the start page (default: 1)
+      --concurrency <n> crawl: requests in flight at once (default: 2)
+      --delay-ms <n>    crawl: minimum time between requests to a host (default: 1000)
+      --max-pages <n>   crawl: stop queueing pages after <n>
+  -h, --help            print this message";
+
+pub const DEFAULT_INPUT: &str = "francis_wiki.html";
+pub const DEFAULT_LANG: &str = "ja";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Fetch { target: String },
+    DumpDom,
+    Tables,
+    Grid(GridArgs),
+    Infobox,
+    Outline,
+    References,
+    Citations,
+    Layout,
+    Render,
+    Crawl(CrawlArgs),
+    Help,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GridArgs {
+    pub table: usize,
+    pub fill: SpanFill,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrawlArgs {
+    pub start: String,
+    pub depth: usize,
+    pub concurrency: usize,
+    pub delay_ms: u64,
+    pub max_pages: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub command: Command,
+    pub input: String,
+    pub output: Option<String>,
+    pub lang: String,
+    pub format: Format,
+    pub passes: Option<String>,
+    pub stylesheets: Vec<String>,
+    pub section: Option<String>,
+    pub full_page: bool,
+    pub verbose: bool,
+    pub cache: Option<String>,
+    pub offline: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ArgError(pub String);
+
+impl Display for ArgError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ArgError {}
+
+impl Command {
+    fn formats(&self) -> &'static [Format] {
+        match self {
+            Command::DumpDom => &[Format::Html, Format::Text],
+            Command::Tables | Command::Layout | Command::Outline | Command::References => {
+                &[Format::Text, Format::Json]
+            }
+            Command::Infobox => &[Format::Json, Format::Text],
+            Command::Citations => &[Format::BibTex, Format::CslJson],
+            Command::Grid(_) => &[Format::Csv, Format::Json, Format::JsonLines],
+            Command::Render => &[Format::Html, Format::Svg],
+            Command::Fetch { .. } | Command::Crawl(_) | Command::Help => &[Format::Html],
+        }
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: String) -> Result<T, ArgError> {
+    value
+        .parse()
+        .map_err(|_| ArgError(format!("`{}` needs a number, not `{}`", flag, value)))
+}
+
+pub fn parse_args<I>(args: I) -> Result<Options, ArgError>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+
+    let mut command_name: Option<String> = None;
+    let mut positional: Vec<String> = vec![];
+    let mut input: Option<String> = None;
+    let mut output: Option<String> = None;
+    let mut lang: Option<String> = None;
+    let mut format: Option<Format> = None;
+    let mut passes: Option<String> = None;
+    let mut stylesheets: Vec<String> = vec![];
+    let mut section: Option<String> = None;
+    let mut full_page = false;
+    let mut verbose = false;
+    let mut cache: Option<String> = None;
+    let mut offline = false;
+    let mut depth: usize = 1;
+    let mut concurrency: usize = 2;
+    let mut delay_ms: u64 = 1000;
+    let mut max_pages: Option<usize> = None;
+    let mut table: usize = 0;
+    let mut fill = SpanFill::Repeat;
+    let mut help = false;
+
+    while let Some(arg) = args.next() {
+        let mut value_for = |flag: &str| {
+            args.next()
+                .ok_or_else(|| ArgError(format!("`{}` needs a value", flag)))
+        };
+
+        match arg.as_str() {
+            "-i" | "--input" => input = Some(value_for(&arg)?),
+            "-o" | "--output" => output = Some(value_for(&arg)?),
+            "-l" | "--lang" => lang = Some(value_for(&arg)?),
+            "-f" | "--format" => format = Some(value_for(&arg)?.parse().map_err(ArgError)?),
+            "--passes" => passes = Some(value_for(&arg)?),
+            "--css" => stylesheets.push(value_for(&arg)?),
+            "--section" => section = Some(value_for(&arg)?),
+            "--full-page" => full_page = true,
+            "-v" | "--verbose" => verbose = true,
+            "--cache" => cache = Some(value_for(&arg)?),
+            "--offline" => offline = true,
+            "--depth" => depth = parse_number(&arg, value_for(&arg)?)?,
+            "--concurrency" => concurrency = parse_number(&arg, value_for(&arg)?)?,
+            "--delay-ms" => delay_ms = parse_number(&arg, value_for(&arg)?)?,
+            "--max-pages" => max_pages = Some(parse_number(&arg, value_for(&arg)?)?),
+            "--table" => table = parse_number(&arg, value_for(&arg)?)?,
+            "--fill" => {
+                fill = match value_for(&arg)?.as_str() {
+                    "repeat" => SpanFill::Repeat,
+                    "empty" => SpanFill::Empty,
+                    other => {
+                        return Err(ArgError(format!(
+                            "`--fill` is `repeat` or `empty`, not `{}`",
+                            other
+                        )))
+                    }
+                }
+            }
+            "-h" | "--help" => help = true,
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(ArgError(format!("unknown option `{}`", arg)));
+            }
+            _ if command_name.is_none() => command_name = Some(arg),
+            _ => positional.push(arg),
+        }
+    }
+
+    let command = match command_name.as_deref() {
+        _ if help => Command::Help,
+        None => Command::Help,
+        Some("fetch") => {
+            if positional.len() != 1 {
+                return Err(ArgError("`fetch` takes one title or URL".to_string()));
+            }
+            Command::Fetch {
+                target: positional.remove(0),
+            }
+        }
+        Some("dump-dom") => Command::DumpDom,
+        Some("tables") => Command::Tables,
+        Some("grid") => Command::Grid(GridArgs { table, fill }),
+        Some("infobox") => Command::Infobox,
+        Some("outline") => Command::Outline,
+        Some("references") => Command::References,
+        Some("citations") => Command::Citations,
+        Some("layout") => Command::Layout,
+        Some("render") => Command::Render,
+        Some("crawl") => {
+            if positional.len() != 1 {
+                return Err(ArgError("`crawl` takes one start title".to_string()));
+            }
+            Command::Crawl(CrawlArgs {
+                start: positional.remove(0),
+                depth,
+                concurrency,
+                delay_ms,
+                max_pages,
+            })
+        }
+        Some(name) => return Err(ArgError(format!("unknown command `{}`", name))),
+    };
+
+    if !positional.is_empty() {
+        return Err(ArgError(format!("unexpected argument `{}`", positional[0])));
+    }
+
+    if offline && cache.is_none() {
+        return Err(ArgError("`--offline` needs `--cache <dir>`".to_string()));
+    }
+
+    let formats = command.formats();
+    let format = format.unwrap_or(formats[0]);
+    if !formats.contains(&format) {
+        return Err(ArgError(format!(
+            "format {:?} is not supported by this command",
+            format
+        )));
+    }
+
+    Ok(Options {
+        command,
+        input: input.unwrap_or_else(|| DEFAULT_INPUT.to_string()),
+        output,
+        lang: lang.unwrap_or_else(|| DEFAULT_LANG.to_string()),
+        format,
+        passes,
+        stylesheets,
+        section,
+        full_page,
+        verbose,
+        cache,
+        offline,
+    })
+}
+
+/// Turns an article title into a Wikipedia URL; URLs are passed through.
+pub fn article_url(target: &str, lang: &str) -> String {
+    if target.starts_with("http://") || target.starts_with("https://") {
+        return target.to_string();
+    }
+
+    mediawiki::page_url(&format!("https://{}.wikipedia.org", lang), target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_subcommands() {
+        let options = parse_args(args("layout -i table.html -f json")).unwrap();
+        assert_eq!(options.command, Command::Layout);
+        assert_eq!(options.input, "table.html");
+        assert_eq!(options.output, None);
+        assert_eq!(options.lang, "ja");
+        assert_eq!(options.format, Format::Json);
+        assert!(!options.full_page);
+
+        let options = parse_args(args("tables --full-page -v --passes rules.txt")).unwrap();
+        assert!(options.full_page && options.verbose);
+        assert_eq!(options.passes, Some("rules.txt".to_string()));
+        assert!(options.stylesheets.is_empty());
+
+        let options = parse_args(args("layout --css mod.css --css mod2.css")).unwrap();
+        assert_eq!(options.stylesheets, ["mod.css", "mod2.css"]);
+
+        let options = parse_args(args("fetch Francis_Drake --lang en -o drake.html")).unwrap();
+        assert_eq!(
+            options.command,
+            Command::Fetch {
+                target: "Francis_Drake".to_string()
+            }
+        );
+        assert_eq!(options.output, Some("drake.html".to_string()));
+        assert_eq!(options.lang, "en");
+
+        let options = parse_args(args("fetch A --cache .cache --offline")).unwrap();
+        assert_eq!(options.cache, Some(".cache".to_string()));
+        assert!(options.offline);
+        assert!(parse_args(args("fetch A --offline")).is_err());
+
+        let options = parse_args(args("crawl A --depth 2 --max-pages 10")).unwrap();
+        assert_eq!(
+            options.command,
+            Command::Crawl(CrawlArgs {
+                start: "A".to_string(),
+                depth: 2,
+                concurrency: 2,
+                delay_ms: 1000,
+                max_pages: Some(10),
+            })
+        );
+        assert!(parse_args(args("crawl A --depth two")).is_err());
+
+        assert!(parse_args(args("render -f json")).is_err());
+        assert!(parse_args(args("tables extra")).is_err());
+        assert!(parse_args(args("dance")).is_err());
+        assert_eq!(parse_args(args("")).unwrap().command, Command::Help);
+    }
+
+    #[test]
+    fn title_to_url() {
+        assert_eq!(
+            article_url("フランシス・ドレーク", "ja"),
+            "https://ja.wikipedia.org/wiki/%E3%83%95%E3%83%A9%E3%83%B3%E3%82%B7%E3%82%B9%E3%83%BB%E3%83%89%E3%83%AC%E3%83%BC%E3%82%AF"
+        );
+        assert_eq!(
+            article_url("Francis Drake", "en"),
+            "https://en.wikipedia.org/wiki/Francis_Drake"
+        );
+        assert_eq!(
+            article_url("https://example.org/x", "en"),
+            "https://example.org/x"
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! Isolating the article from the MediaWiki skin around it.
+//!
+//! A saved Wikipedia page is mostly chrome: the Vector menus, the
+//! interlanguage links, the footer. The article itself is in
+//! `.mw-parser-output`, and even there edit links, the table of contents,
+//! navboxes and `noprint` boxes are not part of the text. A
+//! [`ContentExtractor`] keeps only the article and says what it threw away.
+
+use crate::dom::{self, collect_text, find_elements, get_attr, get_elem_name};
+use crate::select::{Selector, SelectorError};
+use crate::traverse;
+use html5ever::rcdom::{Handle, NodeData};
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+/// Where the article is, most specific first.
+pub const DEFAULT_CONTAINERS: &[&str] = &[
+    ".mw-parser-output",
+    ".mw-body-content",
+    "#mw-content-text",
+    "#bodyContent",
+    "main",
+];
+
+/// Parts of the article that are not article text.
+pub const DEFAULT_CHROME: &[&str] = &[
+    ".mw-editsection",
+    ".mw-jump-link",
+    "#toc",
+    ".toc",
+    ".navbox",
+    ".vertical-navbox",
+    ".noprint",
+    ".mw-indicators",
+    ".catlinks",
+    ".printfooter",
+    ".mw-empty-elt",
+    "#siteSub",
+    "#contentSub",
+];
+
+/// An element the extractor dropped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Removed {
+    /// The chrome selector it matched, or `None` if it was outside the article.
+    pub rule: Option<String>,
+    /// Such as `div#toc.toc`.
+    pub element: String,
+    /// How many characters of text went with it.
+    pub chars: usize,
+}
+
+/// What `ContentExtractor::extract` kept and removed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ContentReport {
+    /// The container selector that found the article, if any did.
+    pub container: Option<String>,
+    pub removed: Vec<Removed>,
+}
+
+impl ContentReport {
+    /// How many elements each rule removed, in the order the rules first
+    /// matched; `None` counts what was outside the article.
+    pub fn counts(&self) -> Vec<(Option<&str>, usize)> {
+        let mut counts: Vec<(Option<&str>, usize)> = vec![];
+        for removed in self.removed.iter() {
+            let rule = removed.rule.as_deref();
+            match counts.iter_mut().find(|(r, _)| *r == rule) {
+                Some((_, n)) => *n += 1,
+                None => counts.push((rule, 1)),
+            }
+        }
+        counts
+    }
+}
+
+impl Display for ContentReport {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self.container {
+            Some(ref container) => writeln!(f, "content: {}", container)?,
+            None => writeln!(f, "content: <body> (no container found)")?,
+        }
+
+        for (rule, n) in self.counts() {
+            let chars: usize = self
+                .removed
+                .iter()
+                .filter(|removed| removed.rule.as_deref() == rule)
+                .map(|removed| removed.chars)
+                .sum();
+            writeln!(
+                f,
+                "removed {:>4} {} ({} chars)",
+                n,
+                rule.unwrap_or("outside the content"),
+                chars
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps the article part of a page and removes the chrome inside it.
+#[derive(Clone, Debug)]
+pub struct ContentExtractor {
+    containers: Vec<(String, Selector)>,
+    chrome: Vec<(String, Selector)>,
+}
+
+impl Default for ContentExtractor {
+    fn default() -> Self {
+        let parse = |selectors: &[&str]| {
+            selectors
+                .iter()
+                .map(|s| (s.to_string(), Selector::parse(s).unwrap()))
+                .collect()
+        };
+
+        ContentExtractor {
+            containers: parse(DEFAULT_CONTAINERS),
+            chrome: parse(DEFAULT_CHROME),
+        }
+    }
+}
+
+impl ContentExtractor {
+    /// An extractor with no rules; add some with `container` and `chrome`.
+    pub fn empty() -> Self {
+        ContentExtractor {
+            containers: vec![],
+            chrome: vec![],
+        }
+    }
+
+    /// Adds a selector for the element holding the article, tried after the others.
+    pub fn container(mut self, selector: &str) -> Result<Self, SelectorError> {
+        self.containers
+            .push((selector.to_string(), Selector::parse(selector)?));
+        Ok(self)
+    }
+
+    /// Adds a selector for elements to remove from the article.
+    pub fn chrome(mut self, selector: &str) -> Result<Self, SelectorError> {
+        self.chrome
+            .push((selector.to_string(), Selector::parse(selector)?));
+        Ok(self)
+    }
+
+    /// Makes the article the only thing in `<body>` and removes the chrome
+    /// from it. `root` is the document element.
+    pub fn extract(&self, root: &Handle) -> ContentReport {
+        let mut report = ContentReport::default();
+        let body = find_elements(root, "body")
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| root.clone());
+
+        if let Some((selector, container)) = self.find_container(&body) {
+            report.container = Some(selector);
+            isolate(&body, &container, &mut report);
+        }
+
+        self.remove_chrome(&body, &mut report);
+        report
+    }
+
+    /// The first container selector that matches anything, and the element
+    /// it matches with the most text (a page can have small
+    /// `.mw-parser-output`s for indicators and the like).
+    fn find_container(&self, body: &Handle) -> Option<(String, Handle)> {
+        for (text, selector) in self.containers.iter() {
+            if let Some(best) = selector
+                .select(body)
+                .into_iter()
+                .max_by_key(|node| collect_text(node).len())
+            {
+                return Some((text.clone(), best));
+            }
+        }
+
+        None
+    }
+
+    fn remove_chrome(&self, node: &Handle, report: &mut ContentReport) {
+        let mut i = 0;
+        while i < node.children.borrow().len() {
+            let child = node.children.borrow()[i].clone();
+
+            match self.chrome.iter().find(|(_, s)| s.matches(&child)) {
+                Some((selector, _)) => {
+                    report.removed.push(removed(&child, Some(selector)));
+                    dom::detach(&child);
+                }
+                None => {
+                    self.remove_chrome(&child, report);
+                    i += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Replaces the children of `body` with `container`, recording every
+/// element that goes.
+fn isolate(body: &Handle, container: &Handle, report: &mut ContentReport) {
+    if Rc::ptr_eq(body, container) {
+        return;
+    }
+
+    // Walk up from the container; the siblings along the way are chrome.
+    let mut path = vec![container.clone()];
+    loop {
+        match path.last().and_then(traverse::parent) {
+            Some(parent) if Rc::ptr_eq(&parent, body) => break,
+            Some(parent) => path.push(parent),
+            None => return,
+        }
+    }
+
+    for (i, ancestor) in path.iter().enumerate().skip(1) {
+        let on_path = &path[i - 1];
+        for child in ancestor.children.borrow().iter() {
+            if !Rc::ptr_eq(child, on_path) && !get_elem_name(child).is_empty() {
+                report.removed.push(removed(child, None));
+            }
+        }
+    }
+    for child in body.children.borrow().iter() {
+        if !Rc::ptr_eq(child, path.last().unwrap()) && !get_elem_name(child).is_empty() {
+            report.removed.push(removed(child, None));
+        }
+    }
+
+    dom::detach(container);
+    let chrome = body.children.borrow().clone();
+    for child in chrome.iter() {
+        dom::detach(child);
+    }
+    dom::append(body, container);
+}
+
+fn removed(node: &Handle, rule: Option<&String>) -> Removed {
+    Removed {
+        rule: rule.cloned(),
+        element: describe(node),
+        chars: collect_text(node).chars().count(),
+    }
+}
+
+/// `tag#id.class1.class2`, as in a selector.
+fn describe(node: &Handle) -> String {
+    let mut s = match node.data {
+        NodeData::Element { .. } => get_elem_name(node),
+        _ => return "#text".to_string(),
+    };
+    if let Some(id) = get_attr(node, "id") {
+        s.push('#');
+        s.push_str(&id);
+    }
+    if let Some(class) = get_attr(node, "class") {
+        for c in class.split_whitespace() {
+            s.push('.');
+            s.push_str(c);
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::{document_element, parse};
+
+    const PAGE: &str = r#"<html><body>
+        <div id="mw-page-base"></div>
+        <div id="content" class="mw-body">
+            <h1 id="firstHeading">フランシス・ドレーク</h1>
+            <div id="bodyContent" class="mw-body-content">
+                <div id="siteSub">出典: フリー百科事典『ウィキペディア』</div>
+                <div id="mw-content-text"><div class="mw-parser-output">
+                    <table class="infobox"><tbody><tr><td>生誕</td></tr></tbody></table>
+                    <p>ドレークは航海者。</p>
+                    <div id="toc" class="toc"><ul><li>1 生涯</li></ul></div>
+                    <h2><span class="mw-headline">生涯</span><span class="mw-editsection">[編集]</span></h2>
+                    <table class="navbox"><tbody><tr><td>海賊</td></tr></tbody></table>
+                </div></div>
+            </div>
+        </div>
+        <div id="mw-navigation"><ul><li class="interlanguage-link">English</li></ul></div>
+    </body></html>"#;
+
+    #[test]
+    fn extract() {
+        let dom = parse(PAGE);
+        let root = document_element(&dom).unwrap();
+        let report = ContentExtractor::default().extract(&root);
+
+        assert_eq!(report.container, Some(".mw-parser-output".to_string()));
+        assert_eq!(
+            report.counts(),
+            vec![
+                (None, 4),
+                (Some("#toc"), 1),
+                (Some(".mw-editsection"), 1),
+                (Some(".navbox"), 1)
+            ]
+        );
+        assert_eq!(report.removed[1].element, "h1#firstHeading");
+        assert_eq!(report.removed[1].chars, 10);
+
+        let body = &find_elements(&root, "body")[0];
+        assert_eq!(body.children.borrow().len(), 1);
+        assert_eq!(find_elements(&root, "table").len(), 1);
+        assert_eq!(collect_text(body), "生誕 ドレークは航海者。 生涯");
+        assert!(report
+            .to_string()
+            .starts_with("content: .mw-parser-output\n"));
+    }
+
+    #[test]
+    fn no_container() {
+        let dom = parse("<p>a<span class=\"noprint\">b</span></p>");
+        let root = document_element(&dom).unwrap();
+        let report = ContentExtractor::default().extract(&root);
+
+        assert_eq!(report.container, None);
+        assert_eq!(collect_text(&root), "a");
+    }
+}
@@ -0,0 +1,380 @@
+//! A breadth-first crawler over internal `/wiki/` links.
+
+use crate::cache::Cache;
+use crate::dom::{find_elements, get_attr};
+use crate::mediawiki;
+use html5ever::rcdom::Handle;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Namespaces skipped by default: `Special:` and `File:` (and their Japanese
+/// and legacy aliases).
+pub const DEFAULT_SKIP_NAMESPACES: &[&str] = &[
+    "Special",
+    "特別",
+    "File",
+    "ファイル",
+    "Image",
+    "画像",
+    "Media",
+    "メディア",
+];
+
+/// Returns the titles of the internal `/wiki/` links under `node`, in
+/// document order and without duplicates or fragments.
+///
+/// This needs the links intact, so it must run before `remove_decoration`.
+pub fn extract_links(node: &Handle, skip_namespaces: &[String]) -> Vec<String> {
+    let mut titles: Vec<String> = vec![];
+
+    for a in find_elements(node, "a") {
+        let href = match get_attr(&a, "href") {
+            Some(href) => href,
+            None => continue,
+        };
+        let path = match href.strip_prefix("/wiki/") {
+            Some(path) => path,
+            None => continue,
+        };
+        let path = path.split('#').next().unwrap_or("");
+        let path = path.split('?').next().unwrap_or("");
+        if path.is_empty() {
+            continue;
+        }
+
+        let title = mediawiki::decode_title(path);
+        if let Some(i) = title.find(':') {
+            let namespace = title[..i].trim();
+            if skip_namespaces
+                .iter()
+                .any(|ns| ns.eq_ignore_ascii_case(namespace))
+            {
+                continue;
+            }
+        }
+
+        if !titles.contains(&title) {
+            titles.push(title);
+        }
+    }
+
+    titles
+}
+
+/// One page reached by the crawler.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrawledPage {
+    pub title: String,
+    pub url: String,
+    pub depth: usize,
+    /// Every internal link on the page, followed or not.
+    pub links: Vec<String>,
+}
+
+/// What a crawl visited and what failed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CrawlReport {
+    pub pages: Vec<CrawledPage>,
+    /// `(title, error message)` for pages that could not be fetched.
+    pub errors: Vec<(String, String)>,
+}
+
+/// Configures and runs a crawl.
+///
+/// Pages are visited level by level up to `max_depth` links away from the
+/// start page, with at most `concurrency` requests in flight and at least
+/// `delay` between two requests to the same host.
+#[derive(Clone)]
+pub struct Crawler {
+    base_url: String,
+    max_depth: usize,
+    max_pages: Option<usize>,
+    concurrency: usize,
+    delay: Duration,
+    skip_namespaces: Vec<String>,
+    cache: Option<Arc<Cache>>,
+    output_dir: Option<PathBuf>,
+}
+
+impl Crawler {
+    pub fn new(lang: &str) -> Self {
+        Crawler {
+            base_url: format!("https://{}.wikipedia.org", lang),
+            max_depth: 1,
+            max_pages: None,
+            concurrency: 2,
+            delay: Duration::from_millis(1000),
+            skip_namespaces: DEFAULT_SKIP_NAMESPACES
+                .iter()
+                .map(|ns| ns.to_string())
+                .collect(),
+            cache: None,
+            output_dir: None,
+        }
+    }
+
+    /// Crawls another server, such as a local test double.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = Some(max_pages);
+        self
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// The minimum time between two requests to the same host.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn skip_namespace(mut self, namespace: &str) -> Self {
+        self.skip_namespaces.push(namespace.to_string());
+        self
+    }
+
+    /// Fetches through `cache`, revalidating entries that are already there.
+    pub fn cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Writes every page to `<dir>/<encoded title>.html`.
+    pub fn output_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.output_dir = Some(dir.into());
+        self
+    }
+
+    pub fn page_url(&self, title: &str) -> String {
+        mediawiki::page_url(&self.base_url, title)
+    }
+}
+
+#[cfg(feature = "fetch")]
+mod run {
+    use super::*;
+    use crate::cache::{self, CachePolicy, CachedResponse};
+    use crate::{dom, source};
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Mutex;
+    use std::time::Instant;
+    use tokio::sync::Semaphore;
+
+    /// Hands out request slots at least `delay` apart per host.
+    struct HostLimiter {
+        delay: Duration,
+        next: Mutex<HashMap<String, Instant>>,
+    }
+
+    impl HostLimiter {
+        async fn wait(&self, url: &str) {
+            let host = url
+                .split("://")
+                .nth(1)
+                .and_then(|rest| rest.split('/').next())
+                .unwrap_or("")
+                .to_string();
+
+            let wait = {
+                let mut next = self.next.lock().unwrap();
+                let now = Instant::now();
+                let slot = match next.get(&host) {
+                    Some(&slot) if slot > now => slot,
+                    _ => now,
+                };
+                next.insert(host, slot + self.delay);
+                slot - now
+            };
+
+            if wait > Duration::from_millis(0) {
+                tokio::time::delay_for(wait).await;
+            }
+        }
+    }
+
+    async fn fetch_page(
+        client: &reqwest::Client,
+        cache: Option<&Cache>,
+        url: &str,
+    ) -> Result<CachedResponse, String> {
+        let response = match cache {
+            Some(cache) => cache::fetch(client, cache, url, CachePolicy::Revalidate).await,
+            None => source::fetch(client, url).await,
+        };
+        response.map_err(|e| e.to_string())
+    }
+
+    /// Everything one page task needs; cloned into each spawned task.
+    #[derive(Clone)]
+    struct PageTask {
+        client: reqwest::Client,
+        limiter: Arc<HostLimiter>,
+        semaphore: Arc<Semaphore>,
+        cache: Option<Arc<Cache>>,
+        output_dir: Option<PathBuf>,
+        skip_namespaces: Vec<String>,
+    }
+
+    impl PageTask {
+        async fn crawl(
+            self,
+            title: String,
+            url: String,
+            depth: usize,
+        ) -> Result<CrawledPage, String> {
+            let _permit = self.semaphore.acquire().await;
+            self.limiter.wait(&url).await;
+
+            let response = fetch_page(&self.client, self.cache.as_deref(), &url).await?;
+
+            // The DOM is not `Send`, so it must not live across an await.
+            let links = {
+                let html = response
+                    .decode()
+                    .unwrap_or_else(|_| String::from_utf8_lossy(&response.body).into_owned());
+                let dom = dom::parse(&html);
+                match dom::document_element(&dom) {
+                    Some(root) => extract_links(&root, &self.skip_namespaces),
+                    None => vec![],
+                }
+            };
+
+            if let Some(dir) = &self.output_dir {
+                let path = dir.join(format!("{}.html", mediawiki::encode_title(&title)));
+                source::save(path, &response).map_err(|e| e.to_string())?;
+            }
+
+            Ok(CrawledPage {
+                title,
+                url,
+                depth,
+                links,
+            })
+        }
+    }
+
+    impl Crawler {
+        /// Crawls outwards from `start`.
+        pub async fn run(&self, start: &str) -> CrawlReport {
+            let task = PageTask {
+                client: reqwest::Client::new(),
+                limiter: Arc::new(HostLimiter {
+                    delay: self.delay,
+                    next: Mutex::new(HashMap::new()),
+                }),
+                semaphore: Arc::new(Semaphore::new(self.concurrency)),
+                cache: self.cache.clone(),
+                output_dir: self.output_dir.clone(),
+                skip_namespaces: self.skip_namespaces.clone(),
+            };
+
+            if let Some(dir) = &self.output_dir {
+                if let Err(e) = std::fs::create_dir_all(dir) {
+                    let mut report = CrawlReport::default();
+                    report.errors.push((start.to_string(), e.to_string()));
+                    return report;
+                }
+            }
+
+            let mut report = CrawlReport::default();
+            let mut seen: HashSet<String> = HashSet::new();
+            let mut frontier = vec![start.to_string()];
+            seen.insert(start.to_string());
+
+            for depth in 0..=self.max_depth {
+                let handles: Vec<_> = frontier
+                    .drain(..)
+                    .map(|title| {
+                        let url = self.page_url(&title);
+                        let task = task.clone();
+                        tokio::spawn(
+                            async move { (title.clone(), task.crawl(title, url, depth).await) },
+                        )
+                    })
+                    .collect();
+
+                for handle in handles {
+                    let (title, result) = match handle.await {
+                        Ok(done) => done,
+                        Err(e) => {
+                            report.errors.push((String::new(), e.to_string()));
+                            continue;
+                        }
+                    };
+
+                    let page = match result {
+                        Ok(page) => page,
+                        Err(e) => {
+                            report.errors.push((title, e));
+                            continue;
+                        }
+                    };
+
+                    if depth < self.max_depth {
+                        for link in page.links.iter() {
+                            if self.max_pages.is_some_and(|max| seen.len() >= max) {
+                                break;
+                            }
+                            if seen.insert(link.clone()) {
+                                frontier.push(link.clone());
+                            }
+                        }
+                    }
+                    report.pages.push(page);
+                }
+
+                if frontier.is_empty() {
+                    break;
+                }
+            }
+
+            report
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::{document_element, parse};
+
+    #[test]
+    fn links() {
+        let html_data = r##"
+        <p>
+            <a href="/wiki/%E3%83%87%E3%83%B4%E3%82%A9%E3%83%B3" title="デヴォン">デヴォン</a>
+            <a href="/wiki/Francis_Drake#Early_life">Drake</a>
+            <a href="/wiki/Francis_Drake">Drake again</a>
+            <a href="/wiki/%E7%89%B9%E5%88%A5:%E6%A4%9C%E7%B4%A2">特別:検索</a>
+            <a href="/wiki/File:Drake.jpg">File</a>
+            <a href="/w/index.php?title=X&amp;action=edit">edit</a>
+            <a href="https://en.wikipedia.org/wiki/Francis_Drake">en</a>
+            <a href="#cite_note-1">[1]</a>
+        </p>
+        "##;
+        let dom = parse(html_data);
+        let root = document_element(&dom).unwrap();
+        let skip: Vec<String> = DEFAULT_SKIP_NAMESPACES
+            .iter()
+            .map(|ns| ns.to_string())
+            .collect();
+
+        assert_eq!(
+            extract_links(&root, &skip),
+            vec!["デヴォン".to_string(), "Francis Drake".to_string()]
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! The HTML string → cleaned DOM → layout entry point.
+
+use crate::cascade::{Cascade, ComputedStyles, Stylesheet};
+use crate::content::{ContentExtractor, ContentReport};
+use crate::dom;
+use crate::infobox::Infobox;
+use crate::layout::{DeviceContext, TestDC};
+use crate::links::LinkTable;
+use crate::passes::{PassContext, PassPipeline};
+use crate::references::{self, Reference};
+use crate::section::Section;
+use crate::select::{Selector, SelectorError};
+use crate::table::Table;
+use crate::text::StyleTable;
+use crate::traverse;
+use html5ever::rcdom::{Handle, RcDom};
+
+/// Configures how HTML is turned into a `Document`.
+///
+/// ```
+/// let doc = francis_wb::Pipeline::new().parse("<table><tbody><tr><td>a</td></tr></tbody></table>");
+/// assert_eq!(doc.tables()[0].cols, 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Pipeline {
+    content: Option<ContentExtractor>,
+    footnotes: bool,
+    passes: PassPipeline,
+    stylesheets: Vec<Stylesheet>,
+    user_agent_styles: bool,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Pipeline::new()
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline {
+            content: None,
+            footnotes: false,
+            passes: PassPipeline::default(),
+            stylesheets: vec![],
+            user_agent_styles: true,
+        }
+    }
+
+    /// Whether to unwrap inline decoration with `dom::remove_decoration` (on by default).
+    pub fn clean(mut self, clean: bool) -> Self {
+        self.passes = if clean {
+            PassPipeline::default()
+        } else {
+            PassPipeline::empty()
+        };
+        self
+    }
+
+    /// Keeps only the article, as found by `extractor`, before cleaning up.
+    pub fn main_content(mut self, extractor: ContentExtractor) -> Self {
+        self.content = Some(extractor);
+        self
+    }
+
+    /// Whether to read the reference lists into `Document::references` and
+    /// replace footnote markers with `a.footnote` anchors (off by default).
+    pub fn footnotes(mut self, footnotes: bool) -> Self {
+        self.footnotes = footnotes;
+        self
+    }
+
+    /// Replaces the cleanup passes run before white space processing.
+    pub fn passes(mut self, passes: PassPipeline) -> Self {
+        self.passes = passes;
+        self
+    }
+
+    /// Adds an external style sheet, such as the one a `<link>` points to.
+    /// Sheets cascade in the order they are added, before the document's
+    /// `<style>` elements.
+    pub fn stylesheet(mut self, sheet: Stylesheet) -> Self {
+        self.stylesheets.push(sheet);
+        self
+    }
+
+    /// Whether the default styles of `cascade::USER_AGENT_CSS` come before
+    /// the page's (on by default). Without them, only the page's own CSS
+    /// decides the layout, as `TestDC` tests may want.
+    pub fn user_agent_styles(mut self, user_agent_styles: bool) -> Self {
+        self.user_agent_styles = user_agent_styles;
+        self
+    }
+
+    pub fn parse(&self, html_data: &str) -> Document {
+        let dom = dom::parse(html_data);
+
+        // Styles are computed before cleanup, while every selector can still match.
+        let mut cascade = if self.user_agent_styles {
+            Cascade::with_user_agent()
+        } else {
+            Cascade::new()
+        };
+        for sheet in self.stylesheets.iter() {
+            cascade.add(sheet);
+        }
+        cascade.add_style_elements(&dom.document);
+        let mut ctx = PassContext {
+            computed: cascade.compute(&dom.document),
+            ..PassContext::default()
+        };
+
+        let mut content = None;
+        let mut references = vec![];
+
+        if let Some(root) = dom::document_element(&dom) {
+            content = self.content.as_ref().map(|c| c.extract(&root));
+            if self.footnotes {
+                references = references::extract(&root);
+            }
+
+            self.passes.run_in(&root, &mut ctx);
+            let PassContext {
+                links,
+                styles,
+                computed,
+            } = &mut ctx;
+            dom::normalize_whitespace_mapped(&root, computed, &mut |text, map| {
+                links.remap(text, map);
+                styles.remap(text, map);
+            });
+        }
+
+        Document {
+            dom,
+            links: ctx.links,
+            styles: ctx.styles,
+            computed: ctx.computed,
+            content,
+            references,
+        }
+    }
+}
+
+/// A parsed (and usually cleaned) HTML document.
+pub struct Document {
+    dom: RcDom,
+    links: LinkTable,
+    styles: StyleTable,
+    computed: ComputedStyles,
+    content: Option<ContentReport>,
+    references: Vec<Reference>,
+}
+
+impl Document {
+    pub fn dom(&self) -> &RcDom {
+        &self.dom
+    }
+
+    /// The links that cleanup flattened into text.
+    pub fn links(&self) -> &LinkTable {
+        &self.links
+    }
+
+    /// The styles of the inline elements that cleanup flattened into text.
+    pub fn styles(&self) -> &StyleTable {
+        &self.styles
+    }
+
+    /// The computed style of every element of the document as parsed.
+    pub fn computed_styles(&self) -> &ComputedStyles {
+        &self.computed
+    }
+
+    /// What `Pipeline::main_content` removed, if it was used.
+    pub fn content_report(&self) -> Option<&ContentReport> {
+        self.content.as_ref()
+    }
+
+    /// The notes of the reference lists, if `Pipeline::footnotes` was on.
+    pub fn references(&self) -> &[Reference] {
+        &self.references
+    }
+
+    /// The document element, normally `<html>`.
+    pub fn root(&self) -> Option<Handle> {
+        dom::document_element(&self.dom)
+    }
+
+    /// The elements matching a CSS selector, such as `table.infobox th[scope=row]`.
+    pub fn select(&self, selector: &str) -> Result<Vec<Handle>, SelectorError> {
+        let selector = Selector::parse(selector)?;
+        Ok(self.root().map_or(vec![], |root| selector.select(&root)))
+    }
+
+    /// Lays out every `<table>` in document order, except those that are
+    /// not drawn because of `display: none`, measuring text with `TestDC`.
+    pub fn tables(&self) -> Vec<Table> {
+        self.tables_measured_by(&TestDC::new())
+    }
+
+    /// Like `tables`, measuring text with `dc`.
+    pub fn tables_measured_by(&self, dc: &dyn DeviceContext) -> Vec<Table> {
+        match self.root() {
+            Some(root) => dom::find_elements(&root, "table")
+                .iter()
+                .filter(|table| !self.computed.get(table).is_some_and(|style| style.hidden))
+                .map(|table| Table::new_from_computed(table, &self.styles, &self.computed, dc))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Every `table.infobox` as key/value records.
+    pub fn infoboxes(&self) -> Vec<Infobox> {
+        match self.root() {
+            Some(root) => Infobox::find_all(&root, &self.computed),
+            None => vec![],
+        }
+    }
+
+    /// The section outline, from the headings of the article body.
+    pub fn sections(&self) -> Vec<Section> {
+        match self.root() {
+            Some(root) => Section::outline(&root, &self.computed),
+            None => vec![],
+        }
+    }
+
+    /// Drops everything in `<body>` but the section a URL fragment points to
+    /// and the elements around it, so that `tables()`, `text()` and the rest
+    /// only see that section.
+    ///
+    /// Returns `false` (and changes nothing) if there is no such section.
+    pub fn keep_section(&self, fragment: &str) -> bool {
+        let sections = self.sections();
+        match Section::find(&sections, fragment) {
+            Some(section) => {
+                section.isolate();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The text content of `<body>`, without scripts, style sheets and
+    /// what `display: none` hides.
+    pub fn text(&self) -> String {
+        let root = match self.root() {
+            Some(root) => root,
+            None => return String::new(),
+        };
+        let body = dom::find_elements(&root, "body").into_iter().next();
+
+        dom::collect_text_except(body.as_ref().unwrap_or(&root), &self.computed, &|node| {
+            let name = traverse::local_name(node);
+            matches!(
+                name.map(|name| &**name),
+                Some("script") | Some("style") | Some("noscript") | Some("template")
+            ) || self.computed.get(node).is_some_and(|style| style.hidden)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Pipeline;
+
+    #[test]
+    fn text() {
+        let doc = Pipeline::new().parse(
+            "<html><head><title>フランシス・ドレーク - Wikipedia</title>\
+             <script>document.documentElement.className = \"client-js\";</script></head>\
+             <body><p>ドレークは<b>航海者</b>。</p><script>RLQ.push(1);</script>\
+             <noscript><img alt=\"\"></noscript><style>p { color: red }</style>\
+             <div style=\"display: none\">隠れた</div><p>生涯</p></body></html>",
+        );
+        assert_eq!(doc.text(), "ドレークは航海者。生涯");
+    }
+
+    #[test]
+    fn white_space_from_styles() {
+        let html = "<style>div.code { white-space: pre } p { white-space: normal }</style>\
+                    <div class=code>a  b</div><pre>c  d</pre><p><textarea>e  f</textarea></p>";
+        assert_eq!(Pipeline::new().parse(html).text(), "a  bc  de  f");
+
+        // Without the default styles, only the page's keep white space.
+        let doc = Pipeline::new().user_agent_styles(false).parse(html);
+        assert_eq!(doc.text(), "a  bc de f");
+    }
+}
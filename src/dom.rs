@@ -0,0 +1,434 @@
+//! Helpers for walking and cleaning an html5ever `RcDom`.
+
+use html5ever::driver::ParseOpts;
+use html5ever::parse_document;
+use html5ever::rcdom::{Handle, NodeData, RcDom};
+use html5ever::tendril::{StrTendril, TendrilSink};
+use html5ever::LocalName;
+use std::ops::Deref;
+use std::rc::Rc;
+
+use crate::cascade::ComputedStyles;
+use crate::passes::PassPipeline;
+use crate::style::parse_declarations;
+use crate::traverse::{self, Edge};
+use crate::whitespace::{self, WhiteSpace};
+
+/// Parses an HTML string into an `RcDom`.
+pub fn parse(html_data: &str) -> RcDom {
+    let parser = parse_document(RcDom::default(), ParseOpts::default());
+    parser.one(html_data)
+}
+
+/// Returns the first element child of the document, normally `<html>`.
+pub fn document_element(dom: &RcDom) -> Option<Handle> {
+    dom.document
+        .children
+        .borrow()
+        .iter()
+        .find(|child| matches!(child.data, NodeData::Element { .. }))
+        .cloned()
+}
+
+/// Takes `node` (with its descendants) out of its parent's children.
+pub fn detach(node: &Handle) {
+    if let Some(parent) = traverse::parent(node) {
+        parent
+            .children
+            .borrow_mut()
+            .retain(|child| !Rc::ptr_eq(child, node));
+    }
+    node.parent.set(None);
+}
+
+/// Makes the detached node `child` the last child of `parent`.
+pub fn append(parent: &Handle, child: &Handle) {
+    debug_assert!(
+        traverse::parent(child).is_none(),
+        "appending an attached node"
+    );
+    child.parent.set(Some(Rc::downgrade(parent)));
+    parent.children.borrow_mut().push(child.clone());
+}
+
+/// Puts the detached node `with` where `node` is, and detaches `node`.
+pub fn replace(node: &Handle, with: &Handle) {
+    let parent = match traverse::parent(node) {
+        Some(parent) => parent,
+        None => return,
+    };
+    let mut children = parent.children.borrow_mut();
+    if let Some(i) = children.iter().position(|child| Rc::ptr_eq(child, node)) {
+        with.parent.set(Some(Rc::downgrade(&parent)));
+        node.parent.set(None);
+        children[i] = with.clone();
+    }
+}
+
+/// Replaces `node` by its children, leaving it detached and empty.
+pub fn unwrap_element(node: &Handle) {
+    let parent = match traverse::parent(node) {
+        Some(parent) => parent,
+        None => return,
+    };
+    let i = match parent
+        .children
+        .borrow()
+        .iter()
+        .position(|child| Rc::ptr_eq(child, node))
+    {
+        Some(i) => i,
+        None => return,
+    };
+
+    let children = node.children.borrow_mut().split_off(0);
+    for child in children.iter() {
+        child.parent.set(Some(Rc::downgrade(&parent)));
+    }
+    node.parent.set(None);
+    parent.children.borrow_mut().splice(i..=i, children);
+}
+
+/// Moves the children of `from` to the end of the children of `to`.
+pub fn move_children(from: &Handle, to: &Handle) {
+    let children = from.children.borrow_mut().split_off(0);
+    for child in children.iter() {
+        child.parent.set(Some(Rc::downgrade(to)));
+    }
+    to.children.borrow_mut().extend(children);
+}
+
+/// Checks that every node under `node` is listed exactly once by its parent
+/// and points back at it.
+pub fn check_parents(node: &Handle) -> Result<(), String> {
+    for parent in std::iter::once(node.clone()).chain(traverse::descendants(node)) {
+        let children = parent.children.borrow();
+        for child in children.iter() {
+            if !traverse::parent(child).is_some_and(|p| Rc::ptr_eq(&p, &parent)) {
+                return Err(format!(
+                    "a child of <{}> does not point back at it",
+                    get_elem_name(&parent)
+                ));
+            }
+            if children.iter().filter(|c| Rc::ptr_eq(c, child)).count() != 1 {
+                return Err(format!(
+                    "a child of <{}> is listed more than once",
+                    get_elem_name(&parent)
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replaces every `elem_name` child of `node` by that child's own children.
+///
+/// Returns `true` if anything was unwrapped.
+pub fn pull_out(node: &Handle, elem_name: &str) -> bool {
+    let matching = node
+        .children
+        .borrow()
+        .iter()
+        .filter(|child| get_elem_name(child) == elem_name)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for child in matching.iter() {
+        unwrap_element(child);
+    }
+
+    !matching.is_empty()
+}
+
+/// Merges runs of adjacent text children of `node` into the first text node
+/// of each run, detaching the others.
+pub fn concatenate_text(node: &Handle) {
+    let mut i = 1;
+    while i < node.children.borrow().len() {
+        let (previous, current) = {
+            let children = node.children.borrow();
+            (children[i - 1].clone(), children[i].clone())
+        };
+        match (&previous.data, &current.data) {
+            (NodeData::Text { contents: first }, NodeData::Text { contents }) => {
+                first.borrow_mut().push_tendril(contents.borrow().deref());
+                node.children.borrow_mut().remove(i);
+                current.parent.set(None);
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+/// Collapses runs of collapsible white space and trims both ends.
+pub fn trim_text(s: &str) -> String {
+    whitespace::trim(&whitespace::process(s, WhiteSpace::Normal)).to_string()
+}
+
+/// The `white-space` value of `node` given the value inherited from its
+/// parent: the one of its style in `computed` or, for an element without
+/// one, the HTML default for its tag overridden by its `style` attribute.
+pub fn element_white_space(
+    node: &Handle,
+    inherited: WhiteSpace,
+    computed: &ComputedStyles,
+) -> WhiteSpace {
+    let name = match traverse::local_name(node) {
+        Some(name) => name,
+        None => return inherited,
+    };
+    if let Some(style) = computed.get(node) {
+        return style.white_space();
+    }
+
+    let mut mode = match &**name {
+        "pre" | "listing" | "plaintext" | "xmp" => WhiteSpace::Pre,
+        "textarea" => WhiteSpace::PreWrap,
+        "nobr" => WhiteSpace::Nowrap,
+        "td" | "th" if get_attr(node, "nowrap").is_some() => WhiteSpace::Nowrap,
+        _ => inherited,
+    };
+
+    if let Some(style) = get_attr(node, "style") {
+        for declaration in parse_declarations(&style) {
+            if declaration.name == "white-space" {
+                mode = WhiteSpace::from_css(&declaration.value).unwrap_or(mode);
+            }
+        }
+    }
+
+    mode
+}
+
+/// The `white-space` value that applies to the children of `node`, found by
+/// walking up through its ancestors.
+pub fn white_space_of(node: &Handle, computed: &ComputedStyles) -> WhiteSpace {
+    let chain: Vec<Handle> = std::iter::once(node.clone())
+        .chain(traverse::ancestors(node))
+        .collect();
+
+    chain
+        .iter()
+        .rev()
+        .fold(WhiteSpace::Normal, |inherited, node| {
+            element_white_space(node, inherited, computed)
+        })
+}
+
+/// Rewrites every text node under `node` according to the `white-space`
+/// value of its element: collapsible runs are collapsed, and preserved
+/// text only has its line endings normalized.
+pub fn normalize_whitespace(node: &Handle) {
+    let computed = ComputedStyles::default();
+    normalize_whitespace_in(node, white_space_of(node, &computed), &computed, &mut None);
+}
+
+/// Called with a text node and the offset map of its rewritten text.
+type Remap<'a> = &'a mut dyn FnMut(&Handle, &[usize]);
+
+/// Like `normalize_whitespace`, taking `white-space` from `computed` and
+/// calling `remap` with every text node and the offset map of
+/// `whitespace::process_mapped`.
+pub fn normalize_whitespace_mapped(
+    node: &Handle,
+    computed: &ComputedStyles,
+    remap: &mut dyn FnMut(&Handle, &[usize]),
+) {
+    normalize_whitespace_in(
+        node,
+        white_space_of(node, computed),
+        computed,
+        &mut Some(remap),
+    );
+}
+
+fn normalize_whitespace_in(
+    node: &Handle,
+    mode: WhiteSpace,
+    computed: &ComputedStyles,
+    remap: &mut Option<Remap>,
+) {
+    if let NodeData::Text { ref contents } = node.data {
+        let processed = match remap {
+            Some(remap) => {
+                let (processed, map) = whitespace::process_mapped(&contents.borrow(), mode);
+                remap(node, &map);
+                processed
+            }
+            None => whitespace::process(&contents.borrow(), mode),
+        };
+        *contents.borrow_mut() = StrTendril::from(processed);
+    }
+
+    for child in node.children.borrow().iter() {
+        let child_mode = element_white_space(child, mode, computed);
+        normalize_whitespace_in(child, child_mode, computed, remap);
+    }
+}
+
+/// Unwraps inline elements (`a`, `b`, `i`, `sup`, `cite`, `span`) under `node`
+/// and merges the resulting text.
+///
+/// Footnote anchors (`a.footnote`) and the heading spans section outlines
+/// read (`span.mw-headline`, `span.mw-editsection`) are kept.
+///
+/// This is `passes::PassPipeline::default()`; build a pipeline to do more.
+pub fn remove_decoration(node: &Handle) {
+    PassPipeline::default().run(node);
+}
+
+/// Returns `node` and all of its descendants named `elem_name`, in document order.
+pub fn find_elements(node: &Handle, elem_name: &str) -> Vec<Handle> {
+    let name = LocalName::from(elem_name);
+    std::iter::once(node.clone())
+        .chain(traverse::descendants(node))
+        .filter(|node| traverse::is_element(node, &name))
+        .collect()
+}
+
+/// Concatenates the text under `node`, following `white-space`.
+///
+/// Collapsible white space is collapsed across text nodes and trimmed from
+/// both ends; preserved white space (`pre`, `pre-wrap`) is kept as is.
+pub fn collect_text(node: &Handle) -> String {
+    collect_text_except(node, &ComputedStyles::default(), &|_| false)
+}
+
+/// Like `collect_text`, taking `white-space` from `computed` and leaving out
+/// the descendants for which `skip` is `true` and everything inside them.
+pub fn collect_text_except(
+    node: &Handle,
+    computed: &ComputedStyles,
+    skip: &dyn Fn(&Handle) -> bool,
+) -> String {
+    let mut text = String::new();
+    // The mode inside each open node, starting with the one inside `node`.
+    let mut modes = vec![];
+    let mut mode = white_space_of(node, computed);
+    // The descendant being skipped, until it is closed.
+    let mut skipped: Option<Handle> = None;
+
+    for edge in traverse::traverse(node).skip(1) {
+        if let Some(ref skipped_node) = skipped {
+            if let Edge::Close(ref child) = edge {
+                if Rc::ptr_eq(child, skipped_node) {
+                    skipped = None;
+                }
+            }
+            continue;
+        }
+
+        match edge {
+            Edge::Open(child) if skip(&child) => skipped = Some(child),
+            Edge::Open(child) => {
+                if let NodeData::Text { ref contents } = child.data {
+                    let processed = whitespace::process(&contents.borrow(), mode);
+                    let processed = if mode.collapses_spaces() && text.ends_with(' ') {
+                        processed.trim_start_matches(' ')
+                    } else {
+                        &processed
+                    };
+                    text.push_str(processed);
+                }
+                modes.push(mode);
+                mode = element_white_space(&child, mode, computed);
+            }
+            Edge::Close(_) => mode = modes.pop().unwrap_or(mode),
+        }
+    }
+
+    whitespace::trim(&text).to_string()
+}
+
+/// Returns the local name of an element, or an empty string for other nodes.
+///
+/// This copies the name; `traverse::local_name` borrows it.
+pub fn get_elem_name(node: &Handle) -> String {
+    traverse::local_name(node).map_or_else(String::new, |name| name.to_string())
+}
+
+/// Returns the value of the attribute `attr_name`, if `node` is an element that has it.
+pub fn get_attr(node: &Handle, attr_name: &str) -> Option<String> {
+    if let NodeData::Element { ref attrs, .. } = node.data {
+        for attr in attrs.borrow().iter() {
+            if &*attr.name.local == attr_name {
+                return Some(attr.value.to_string());
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_follows_white_space() {
+        let dom = parse(
+            "<div>\n  <p>イングランド、\n  デヴォン</p>\n  <pre>a  b\n  c</pre>\n  \
+             <p style=\"white-space: pre-line\">x  \n y</p><p>\u{3000}z\u{a0}</p>\n</div>",
+        );
+        let root = document_element(&dom).unwrap();
+        let p = find_elements(&root, "p");
+        let pre = find_elements(&root, "pre");
+
+        assert_eq!(collect_text(&p[0]), "イングランド、デヴォン");
+        assert_eq!(collect_text(&pre[0]), "a  b\n  c");
+        assert_eq!(collect_text(&p[1]), "x\ny");
+        assert_eq!(collect_text(&p[2]), "\u{3000}z\u{a0}");
+
+        normalize_whitespace(&root);
+        let div = &find_elements(&root, "div")[0];
+        assert_eq!(
+            collect_text(div),
+            "イングランド、デヴォン a  b\n  c x\ny\u{3000}z\u{a0}"
+        );
+        assert_eq!(
+            get_text(&pre[0].children.borrow()[0]),
+            Some("a  b\n  c".to_string())
+        );
+    }
+
+    #[test]
+    fn mutations() {
+        let dom = parse("<div><p>a<b>b<i>c</i></b>d</p><ul><li>1</li><li>2</li></ul></div>");
+        let root = document_element(&dom).unwrap();
+        let p = find_elements(&root, "p")[0].clone();
+        let b = find_elements(&root, "b")[0].clone();
+        let li = find_elements(&root, "li");
+
+        assert!(pull_out(&p, "b"));
+        assert!(traverse::parent(&b).is_none());
+        assert!(b.children.borrow().is_empty());
+        assert_eq!(p.children.borrow().len(), 4);
+
+        let i = find_elements(&p, "i")[0].clone();
+        unwrap_element(&i);
+        concatenate_text(&p);
+        assert_eq!(p.children.borrow().len(), 1);
+        assert_eq!(get_text(&p.children.borrow()[0]), Some("abcd".to_string()));
+
+        replace(&li[0], &b);
+        detach(&li[1]);
+        append(&p, &li[1]);
+        assert!(traverse::parent(&li[0]).is_none());
+        assert_eq!(collect_text(&root), "abcd2");
+        assert_eq!(check_parents(&dom.document), Ok(()));
+
+        // `b` is still listed by the `ul` it replaced the first item of.
+        p.children.borrow_mut().push(b.clone());
+        assert_eq!(
+            check_parents(&root),
+            Err("a child of <p> does not point back at it".to_string())
+        );
+    }
+
+    fn get_text(node: &Handle) -> Option<String> {
+        match node.data {
+            NodeData::Text { ref contents } => Some(contents.borrow().to_string()),
+            _ => None,
+        }
+    }
+}
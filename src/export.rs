@@ -0,0 +1,575 @@
+//! Writers for tables and their layout.
+
+use crate::bibliography;
+use crate::infobox::Infobox;
+use crate::layout::{Point, Region, TestDC};
+use crate::references::Reference;
+use crate::section::Section;
+use crate::table::{Table, TableGrid};
+use crate::text::TextRun;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// Output formats understood by the writers in this module.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Html,
+    Svg,
+    Csv,
+    /// One JSON value per line.
+    JsonLines,
+    BibTex,
+    CslJson,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "html" => Ok(Format::Html),
+            "svg" => Ok(Format::Svg),
+            "csv" => Ok(Format::Csv),
+            "jsonl" => Ok(Format::JsonLines),
+            "bibtex" => Ok(Format::BibTex),
+            "csl-json" => Ok(Format::CslJson),
+            _ => Err(format!("unknown format `{}`", s)),
+        }
+    }
+}
+
+/// Escapes text for use in HTML or SVG content and attribute values.
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes the grid of each table: `Display` output for `Text`, or JSON.
+pub fn write_tables(out: &mut dyn Write, tables: &[Table], format: Format) -> io::Result<()> {
+    match format {
+        Format::Json => {
+            let json: Vec<_> = tables
+                .iter()
+                .map(|table| {
+                    serde_json::json!({
+                        "rows": table.rows,
+                        "cols": table.cols,
+                        "cells": table.cells.iter().map(|cell| serde_json::json!({
+                            "row_range": cell.row_range,
+                            "col_range": cell.col_range,
+                            "text": cell.text_block.text,
+                        })).collect::<Vec<_>>(),
+                    })
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut *out, &json)?;
+            writeln!(out)
+        }
+        _ => {
+            for table in tables {
+                writeln!(out, "{:}", table)?;
+                writeln!(out, "------------------------------")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Writes the section tree as indented `level title #anchor` lines for
+/// `Text`, or as nested JSON.
+pub fn write_outline(out: &mut dyn Write, sections: &[Section], format: Format) -> io::Result<()> {
+    fn write_text(out: &mut dyn Write, sections: &[Section], depth: usize) -> io::Result<()> {
+        for section in sections {
+            writeln!(
+                out,
+                "{}h{} {} #{}",
+                "  ".repeat(depth),
+                section.level,
+                section.title,
+                section.anchor
+            )?;
+            write_text(out, &section.children, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    if format == Format::Json {
+        serde_json::to_writer_pretty(&mut *out, sections)?;
+        writeln!(out)
+    } else {
+        write_text(out, sections, 0)
+    }
+}
+
+/// Writes the notes as `[marker] text` lines for `Text`, or as JSON.
+pub fn write_references(
+    out: &mut dyn Write,
+    references: &[Reference],
+    format: Format,
+) -> io::Result<()> {
+    if format == Format::Json {
+        serde_json::to_writer_pretty(&mut *out, references)?;
+        return writeln!(out);
+    }
+
+    for reference in references {
+        writeln!(out, "[{}] {}", reference.marker, reference.text)?;
+    }
+    Ok(())
+}
+
+/// Writes the `<cite>` citations of the notes as BibTeX entries or a
+/// CSL-JSON array.
+pub fn write_citations(
+    out: &mut dyn Write,
+    references: &[Reference],
+    format: Format,
+) -> io::Result<()> {
+    let entries = bibliography::entries(references);
+    if format == Format::CslJson {
+        let items: Vec<_> = entries.iter().map(bibliography::csl_item).collect();
+        serde_json::to_writer_pretty(&mut *out, &items)?;
+        return writeln!(out);
+    }
+
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        write!(out, "{}", bibliography::bibtex_entry(entry))?;
+    }
+    Ok(())
+}
+
+/// Writes each infobox as `key: value` lines for `Text`, or as JSON.
+///
+/// Values from `<br>`-separated lines are joined with ` / ` in text output.
+pub fn write_infoboxes(
+    out: &mut dyn Write,
+    infoboxes: &[Infobox],
+    format: Format,
+) -> io::Result<()> {
+    if format == Format::Json {
+        serde_json::to_writer_pretty(&mut *out, infoboxes)?;
+        return writeln!(out);
+    }
+
+    for infobox in infoboxes {
+        writeln!(out, "# {}", infobox.title.as_deref().unwrap_or(""))?;
+        for image in infobox.images.iter() {
+            writeln!(out, "image: {}", image.src)?;
+        }
+
+        let mut section = None;
+        for field in infobox.fields.iter() {
+            if field.section != section {
+                section = field.section.clone();
+                writeln!(out, "## {}", section.as_deref().unwrap_or(""))?;
+            }
+            match field.key {
+                Some(ref key) => writeln!(out, "{}: {}", key, field.values.join(" / "))?,
+                None => writeln!(out, "{}", field.values.join(" / "))?,
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Quotes a CSV field if it needs it (RFC 4180).
+pub fn escape_csv(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// A row as a JSON object keyed by column name, in column order.
+struct Record<'a> {
+    names: &'a [String],
+    values: &'a [String],
+}
+
+impl Serialize for Record<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.names.len()))?;
+        for (name, value) in self.names.iter().zip(self.values.iter()) {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+/// A row of a grid as JSON: a `Record` if the grid has a header.
+enum GridRow<'a> {
+    Record(Record<'a>),
+    Values(&'a [String]),
+}
+
+impl Serialize for GridRow<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            GridRow::Record(record) => record.serialize(serializer),
+            GridRow::Values(values) => values.serialize(serializer),
+        }
+    }
+}
+
+/// Column names usable as JSON keys: blanks become `column N` and repeats get a suffix.
+fn column_names(header: &[String]) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    for (i, name) in header.iter().enumerate() {
+        let base = if name.is_empty() {
+            format!("column {}", i + 1)
+        } else {
+            name.clone()
+        };
+        let mut name = base.clone();
+        let mut n = 2;
+        while names.contains(&name) {
+            name = format!("{} ({})", base, n);
+            n += 1;
+        }
+        names.push(name);
+    }
+    names
+}
+
+/// Writes a table grid as CSV (header line first), a JSON array of rows, or
+/// JSON Lines. JSON rows are objects keyed by column name when the table has
+/// a header, and arrays otherwise.
+pub fn write_grid(out: &mut dyn Write, grid: &TableGrid, format: Format) -> io::Result<()> {
+    let names = grid.header.as_deref().map(column_names);
+
+    match format {
+        Format::Csv => {
+            for row in grid.header.iter().chain(grid.rows.iter()) {
+                let fields: Vec<String> = row.iter().map(|s| escape_csv(s)).collect();
+                writeln!(out, "{}", fields.join(","))?;
+            }
+            Ok(())
+        }
+        Format::Json | Format::JsonLines => {
+            let rows: Vec<GridRow> = grid
+                .rows
+                .iter()
+                .map(|row| match names {
+                    Some(ref names) => GridRow::Record(Record { names, values: row }),
+                    None => GridRow::Values(row),
+                })
+                .collect();
+
+            if format == Format::Json {
+                serde_json::to_writer_pretty(&mut *out, &rows)?;
+                writeln!(out)
+            } else {
+                for row in rows.iter() {
+                    serde_json::to_writer(&mut *out, row)?;
+                    writeln!(out)?;
+                }
+                Ok(())
+            }
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot write a table grid as {:?}", format),
+        )),
+    }
+}
+
+/// Writes the position and size of every cell: one line per cell for `Text`, or JSON.
+pub fn write_layout(out: &mut dyn Write, tables: &[Table], format: Format) -> io::Result<()> {
+    match format {
+        Format::Json => {
+            let json: Vec<_> = tables
+                .iter()
+                .map(|table| {
+                    serde_json::json!({
+                        "width": table.size.width,
+                        "height": table.size.height,
+                        "cells": table.cells.iter().map(|cell| {
+                            let pos = cell.text_block.pos.get();
+                            let size = cell.text_block.size.get();
+                            serde_json::json!({
+                                "x": pos.x,
+                                "y": pos.y,
+                                "width": size.width,
+                                "height": size.height,
+                                "text": cell.text_block.text,
+                            })
+                        }).collect::<Vec<_>>(),
+                    })
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut *out, &json)?;
+            writeln!(out)
+        }
+        _ => {
+            for (i, table) in tables.iter().enumerate() {
+                writeln!(
+                    out,
+                    "table {}: {}x{}",
+                    i, table.size.width, table.size.height
+                )?;
+                for cell in table.cells.iter() {
+                    let pos = cell.text_block.pos.get();
+                    let size = cell.text_block.size.get();
+                    writeln!(
+                        out,
+                        "  ({}, {}) {}x{} {:?}",
+                        pos.x, pos.y, size.width, size.height, cell.text_block.text
+                    )?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+const RENDER_MARGIN: u32 = 20;
+
+/// The runs as escaped text, with `<span>` (or SVG `<tspan>`) around the styled ones.
+fn styled_runs(runs: &[TextRun], format: Format) -> String {
+    let mut out = String::new();
+    for run in runs {
+        let text = escape_html(&run.text);
+        let style = &run.style;
+
+        let mut props: Vec<(&str, String)> = vec![];
+        if style.bold {
+            props.push(("font-weight", "bold".to_string()));
+        }
+        if style.italic {
+            props.push(("font-style", "italic".to_string()));
+        }
+        if style.superscript {
+            props.push(("baseline-shift", "super".to_string()));
+        }
+        if let Some(size) = style.font_size {
+            props.push(("font-size", format!("{}px", size)));
+        }
+
+        if props.is_empty() && style.lang.is_none() {
+            out.push_str(&text);
+            continue;
+        }
+
+        let lang = match style.lang {
+            Some(ref lang) if format == Format::Svg => {
+                format!(r#" xml:lang="{}""#, escape_html(lang))
+            }
+            Some(ref lang) => format!(r#" lang="{}""#, escape_html(lang)),
+            None => String::new(),
+        };
+        if format == Format::Svg {
+            let attrs: String = props
+                .iter()
+                .map(|(k, v)| format!(r#" {}="{}""#, k, v))
+                .collect();
+            out.push_str(&format!("<tspan{}{}>{}</tspan>", lang, attrs, text));
+        } else {
+            let css: Vec<String> = props
+                .iter()
+                .map(|(k, v)| match *k {
+                    "baseline-shift" => "vertical-align:super".to_string(),
+                    _ => format!("{}:{}", k, v),
+                })
+                .collect();
+            let css = if css.is_empty() {
+                String::new()
+            } else {
+                format!(r#" style="{}""#, css.join("; "))
+            };
+            out.push_str(&format!("<span{}{}>{}</span>", lang, css, text));
+        }
+    }
+    out
+}
+
+/// Draws every table as absolutely positioned HTML boxes, or as SVG for `Format::Svg`.
+pub fn write_render(out: &mut dyn Write, tables: &[Table], format: Format) -> io::Result<()> {
+    let region = |table: &Table| {
+        table.edges.margin_region(Region {
+            pos: Point::new(),
+            size: table.size,
+        })
+    };
+    let width = tables
+        .iter()
+        .map(|t| region(t).size.width)
+        .max()
+        .unwrap_or(0)
+        + 2 * RENDER_MARGIN;
+    let height = tables
+        .iter()
+        .map(|t| region(t).size.height + RENDER_MARGIN)
+        .sum::<u32>()
+        + RENDER_MARGIN;
+
+    if format == Format::Svg {
+        writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}">"#,
+            width, height
+        )?;
+    } else {
+        writeln!(out, "<!DOCTYPE html>")?;
+        writeln!(out, r#"<html><head><meta charset="UTF-8" /></head><body>"#)?;
+    }
+
+    let mut top = RENDER_MARGIN;
+    for table in tables {
+        // The table's border box, inside its margins.
+        let origin = Point {
+            x: RENDER_MARGIN as i32 - region(table).pos.x,
+            y: top as i32 - region(table).pos.y,
+        };
+        for cell in table.cells.iter() {
+            let pos = cell.text_block.pos.get();
+            let size = cell.text_block.size.get();
+            let content = cell.content_region();
+            let x = origin.x + pos.x;
+            let y = origin.y + pos.y;
+            let text = styled_runs(&cell.text_block.runs, format);
+            // Glyphs as large as the ones the layout was measured with.
+            let font_size = TestDC::glyph_size(&cell.text_block.style);
+
+            if format == Format::Svg {
+                writeln!(
+                    out,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="gray" />"#,
+                    x, y, size.width, size.height
+                )?;
+                writeln!(
+                    out,
+                    r#"<text x="{}" y="{}" font-size="{}">{}</text>"#,
+                    origin.x + content.pos.x,
+                    origin.y + content.pos.y + content.size.height as i32,
+                    font_size,
+                    text
+                )?;
+            } else {
+                // The cell's border and padding, so the text sits in its content box.
+                let inset = (
+                    content.pos.y - pos.y,
+                    (pos.x + size.width as i32) - (content.pos.x + content.size.width as i32),
+                    (pos.y + size.height as i32) - (content.pos.y + content.size.height as i32),
+                    content.pos.x - pos.x,
+                );
+                writeln!(
+                    out,
+                    r#"<div style="position:absolute; left:{}px; top:{}px; width:{}px; height:{}px; padding:{}px {}px {}px {}px; box-sizing:border-box; outline:1px solid gray; font-size:{}px;">{}</div>"#,
+                    x,
+                    y,
+                    size.width,
+                    size.height,
+                    inset.0,
+                    inset.1,
+                    inset.2,
+                    inset.3,
+                    font_size,
+                    text
+                )?;
+            }
+        }
+        top += region(table).size.height + RENDER_MARGIN;
+    }
+
+    if format == Format::Svg {
+        writeln!(out, "</svg>")
+    } else {
+        writeln!(out, "</body></html>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::SpanFill;
+    use crate::Pipeline;
+
+    const HTML: &str = r##"
+        <table>
+        <tbody>
+            <tr><th>年</th><th></th><th>艦名</th><th>艦名</th></tr>
+            <tr><td rowspan="2">1577年</td><td>a, b</td><td>"ペリカン"</td><td>x</td></tr>
+            <tr><td colspan="3">ゴールデン・ハインド</td></tr>
+        </tbody>
+        </table>
+        "##;
+
+    fn grid(fill: SpanFill, format: Format) -> String {
+        let table = &Pipeline::new().parse(HTML).tables()[0];
+        let mut out = vec![];
+        write_grid(&mut out, &table.grid(fill), format).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv_quoting() {
+        assert_eq!(escape_csv("ドレーク"), "ドレーク");
+        assert_eq!(escape_csv("a, b"), "\"a, b\"");
+        assert_eq!(escape_csv("\"ペリカン\"号"), "\"\"\"ペリカン\"\"号\"");
+        assert_eq!(escape_csv("1577\n1580"), "\"1577\n1580\"");
+        assert_eq!(escape_csv("a\rb"), "\"a\rb\"");
+    }
+
+    #[test]
+    fn column_names_are_unique() {
+        let header: Vec<String> = ["年", "", "艦名", "艦名", "艦名", ""]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        assert_eq!(
+            column_names(&header),
+            ["年", "column 2", "艦名", "艦名 (2)", "艦名 (3)", "column 6"]
+        );
+    }
+
+    #[test]
+    fn csv_span_fill() {
+        assert_eq!(
+            grid(SpanFill::Repeat, Format::Csv),
+            "年,,艦名,艦名\n\
+             1577年,\"a, b\",\"\"\"ペリカン\"\"\",x\n\
+             1577年,ゴールデン・ハインド,ゴールデン・ハインド,ゴールデン・ハインド\n"
+        );
+        assert_eq!(
+            grid(SpanFill::Empty, Format::Csv),
+            "年,,艦名,艦名\n\
+             1577年,\"a, b\",\"\"\"ペリカン\"\"\",x\n\
+             ,ゴールデン・ハインド,,\n"
+        );
+    }
+
+    #[test]
+    fn json_lines() {
+        let jsonl = grid(SpanFill::Empty, Format::JsonLines);
+        let lines: Vec<&str> = jsonl.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                r#"{"年":"1577年","column 2":"a, b","艦名":"\"ペリカン\"","艦名 (2)":"x"}"#,
+                r#"{"年":"","column 2":"ゴールデン・ハインド","艦名":"","艦名 (2)":""}"#,
+            ]
+        );
+        for line in lines {
+            assert!(serde_json::from_str::<serde_json::Value>(line)
+                .unwrap()
+                .is_object());
+        }
+
+        let json: serde_json::Value =
+            serde_json::from_str(&grid(SpanFill::Repeat, Format::Json)).unwrap();
+        assert_eq!(json[1]["艦名 (2)"], "ゴールデン・ハインド");
+        assert!(write_grid(&mut vec![], &TableGrid::default(), Format::Svg).is_err());
+    }
+}
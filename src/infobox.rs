@@ -0,0 +1,304 @@
+//! Infoboxes as ordered key/value records.
+//!
+//! An infobox is a `table.infobox` whose rows are, in order: a title (one
+//! `th` spanning the table), images and other full-width `td` rows,
+//! full-width `th` rows heading a group of fields, and `th`/`td` pairs.
+
+use crate::cascade::ComputedStyles;
+use crate::dom::{get_attr, get_elem_name, white_space_of};
+use crate::select::Selector;
+use crate::whitespace;
+use html5ever::rcdom::{Handle, NodeData};
+use serde::Serialize;
+
+/// An image in a full-width row.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct InfoboxImage {
+    pub src: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// The text of the row the image is in, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+}
+
+/// One row of an infobox.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct InfoboxField {
+    /// The heading of the group of rows this one is in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+    /// The `th` text; `None` for full-width rows.
+    pub key: Option<String>,
+    /// The cell text, one entry per `<br>`- or list-separated line.
+    pub values: Vec<String>,
+}
+
+/// The contents of a `table.infobox`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Infobox {
+    pub title: Option<String>,
+    pub images: Vec<InfoboxImage>,
+    pub fields: Vec<InfoboxField>,
+}
+
+impl Infobox {
+    /// Every `table.infobox` under `node`, in document order.
+    pub fn find_all(node: &Handle, computed: &ComputedStyles) -> Vec<Infobox> {
+        Selector::parse("table.infobox")
+            .unwrap()
+            .select(node)
+            .iter()
+            .map(|table| Infobox::from_table(table, computed))
+            .collect()
+    }
+
+    /// Reads the rows of `table_node`, following the `white-space` of the
+    /// cells in `computed`.
+    pub fn from_table(table_node: &Handle, computed: &ComputedStyles) -> Infobox {
+        let mut infobox = Infobox::default();
+        let mut section: Option<String> = None;
+
+        if let Some(caption) = table_node
+            .children
+            .borrow()
+            .iter()
+            .find(|child| get_elem_name(child) == "caption")
+        {
+            infobox.title = Some(lines(caption, computed).join(" ")).filter(|s| !s.is_empty());
+        }
+
+        let noprint = Selector::parse(".noprint").unwrap();
+        for row in rows(table_node) {
+            if noprint.matches(&row) {
+                continue;
+            }
+
+            let cells: Vec<Handle> = row
+                .children
+                .borrow()
+                .iter()
+                .filter(|child| matches!(get_elem_name(child).as_str(), "th" | "td"))
+                .cloned()
+                .collect();
+
+            match cells.as_slice() {
+                [cell] if get_elem_name(cell) == "th" => {
+                    let text = lines(cell, computed).join(" ");
+                    if text.is_empty() {
+                        continue;
+                    }
+                    if infobox.title.is_none() && infobox.fields.is_empty() {
+                        infobox.title = Some(text);
+                    } else {
+                        section = Some(text);
+                    }
+                }
+                [cell] => {
+                    let mut images = vec![];
+                    find_images(cell, &mut images);
+                    let values = lines(cell, computed);
+
+                    if images.is_empty() {
+                        if !values.is_empty() {
+                            infobox.fields.push(InfoboxField {
+                                section: section.clone(),
+                                key: None,
+                                values,
+                            });
+                        }
+                    } else {
+                        let caption = Some(values.join(" ")).filter(|s| !s.is_empty());
+                        for img in images.iter() {
+                            infobox.images.push(image(img, caption.clone()));
+                        }
+                    }
+                }
+                [key, value, ..] => {
+                    let key = lines(key, computed).join(" ");
+                    let values = lines(value, computed);
+                    if key.is_empty() && values.is_empty() {
+                        continue;
+                    }
+                    infobox.fields.push(InfoboxField {
+                        section: section.clone(),
+                        key: Some(key).filter(|k| !k.is_empty()),
+                        values,
+                    });
+                }
+                [] => {}
+            }
+        }
+
+        infobox
+    }
+
+    /// The values of the first field called `key`.
+    pub fn get(&self, key: &str) -> Option<&[String]> {
+        self.fields
+            .iter()
+            .find(|field| field.key.as_deref() == Some(key))
+            .map(|field| field.values.as_slice())
+    }
+}
+
+/// The rows of a table, not counting those of tables nested in its cells.
+fn rows(table_node: &Handle) -> Vec<Handle> {
+    let mut rows = vec![];
+    for child in table_node.children.borrow().iter() {
+        match get_elem_name(child).as_str() {
+            "tr" => rows.push(child.clone()),
+            "thead" | "tbody" | "tfoot" => rows.extend(
+                child
+                    .children
+                    .borrow()
+                    .iter()
+                    .filter(|row| get_elem_name(row) == "tr")
+                    .cloned(),
+            ),
+            _ => {}
+        }
+    }
+    rows
+}
+
+fn find_images(node: &Handle, found: &mut Vec<Handle>) {
+    if get_elem_name(node) == "img" {
+        found.push(node.clone());
+    }
+    for child in node.children.borrow().iter() {
+        find_images(child, found);
+    }
+}
+
+fn image(img: &Handle, caption: Option<String>) -> InfoboxImage {
+    let number = |name: &str| get_attr(img, name).and_then(|v| v.trim().parse().ok());
+
+    InfoboxImage {
+        src: get_attr(img, "src").unwrap_or_default(),
+        alt: get_attr(img, "alt"),
+        width: number("width"),
+        height: number("height"),
+        caption,
+    }
+}
+
+/// The text of `cell`, split at `<br>`s and around block elements, with
+/// white space collapsed and empty lines dropped.
+fn lines(cell: &Handle, computed: &ComputedStyles) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    collect_lines(cell, &mut lines);
+
+    lines
+        .iter()
+        .map(|line| {
+            whitespace::trim(&whitespace::process(line, white_space_of(cell, computed))).to_string()
+        })
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+fn collect_lines(node: &Handle, lines: &mut Vec<String>) {
+    match node.data {
+        NodeData::Text { ref contents } => {
+            lines.last_mut().unwrap().push_str(&contents.borrow());
+            return;
+        }
+        NodeData::Element { .. } => {}
+        _ => return,
+    }
+
+    let name = get_elem_name(node);
+    if name == "br" {
+        lines.push(String::new());
+        return;
+    }
+
+    let block = matches!(
+        name.as_str(),
+        "li" | "p" | "div" | "ul" | "ol" | "dd" | "dt"
+    );
+    if block {
+        lines.push(String::new());
+    }
+    for child in node.children.borrow().iter() {
+        collect_lines(child, lines);
+    }
+    if block {
+        lines.push(String::new());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pipeline;
+
+    #[test]
+    fn infobox() {
+        let html_data = r#"
+        <table class="infobox" style="width: 300px;"><tbody>
+            <tr><th colspan="2">フランシス・ドレーク</th></tr>
+            <tr><td colspan="2"><i><a href="/wiki/1543年">1543年</a>頃 - 1596年1月28日</i></td></tr>
+            <tr><td colspan="2"><a href="/wiki/File:Francis_drake.jpg" class="image"><img alt="Francis drake.jpg"
+                src="//upload.wikimedia.org/200px-Francis_drake.jpg" width="200" height="237" /></a>
+                <div>1591年の肖像</div></td></tr>
+            <tr><th>渾名</th><td>
+                エル・ドラケ（<span lang="es">El Draque</span>）<br />ドラコ（<span lang="la">Draco</span>）</td></tr>
+            <tr><th colspan="2">軍歴</th></tr>
+            <tr><th>最終階級</th><td><ul><li>イギリス海軍中将</li><li>ナイト</li></ul></td></tr>
+            <tr class="noprint"><td colspan="2"><a href="/wiki/Template:X">テンプレートを表示</a></td></tr>
+        </tbody></table>"#;
+
+        for pipeline in [Pipeline::new(), Pipeline::new().clean(false)].iter() {
+            let doc = pipeline.parse(html_data);
+            let infoboxes = doc.infoboxes();
+            assert_eq!(infoboxes.len(), 1);
+
+            let infobox = &infoboxes[0];
+            assert_eq!(infobox.title.as_deref(), Some("フランシス・ドレーク"));
+            assert_eq!(
+                infobox.images,
+                vec![InfoboxImage {
+                    src: "//upload.wikimedia.org/200px-Francis_drake.jpg".to_string(),
+                    alt: Some("Francis drake.jpg".to_string()),
+                    width: Some(200),
+                    height: Some(237),
+                    caption: Some("1591年の肖像".to_string()),
+                }]
+            );
+            assert_eq!(infobox.fields.len(), 3);
+            assert_eq!(infobox.fields[0].key, None);
+            assert_eq!(infobox.fields[0].values, vec!["1543年頃 - 1596年1月28日"]);
+            assert_eq!(
+                infobox.get("渾名").unwrap(),
+                &["エル・ドラケ（El Draque）", "ドラコ（Draco）"]
+            );
+            assert_eq!(infobox.fields[2].section.as_deref(), Some("軍歴"));
+            assert_eq!(
+                infobox.get("最終階級").unwrap(),
+                &["イギリス海軍中将", "ナイト"]
+            );
+
+            let json = serde_json::to_value(infobox).unwrap();
+            assert_eq!(json["fields"][1]["key"], "渾名");
+            assert_eq!(json["fields"][1].get("section"), None);
+            assert_eq!(json["images"][0]["width"], 200);
+        }
+    }
+
+    #[test]
+    fn white_space_from_styles() {
+        let html_data = "<style>td.code { white-space: pre }</style>\
+            <table class=infobox><tr><th>書式</th><td class=code>a  b</td></tr>\
+            <tr><th>名  前</th><td>c  d</td></tr></table>";
+        let doc = Pipeline::new().parse(html_data);
+        let infobox = &doc.infoboxes()[0];
+        assert_eq!(infobox.get("書式").unwrap(), &["a  b"]);
+        assert_eq!(infobox.get("名 前").unwrap(), &["c d"]);
+    }
+}
@@ -0,0 +1,417 @@
+//! Geometry primitives, the block tree and text measurement.
+
+use crate::text::{TextRun, TextStyle};
+use std::{
+    cell::{Cell, RefCell},
+    rc::{Rc, Weak},
+};
+
+/// A width and height in pixels.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Size {
+    pub fn new() -> Self {
+        Size {
+            width: 0,
+            height: 0,
+        }
+    }
+}
+
+/// A position in pixels relative to the containing block.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    pub fn new() -> Self {
+        Point { x: 0, y: 0 }
+    }
+}
+
+/// A positioned rectangle.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Region {
+    pub pos: Point,
+    pub size: Size,
+}
+
+impl Region {
+    pub fn new() -> Self {
+        Region {
+            pos: Point::new(),
+            size: Size::new(),
+        }
+    }
+}
+
+/// Something on each of the four sides of a box.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Edges<T> {
+    pub top: T,
+    pub right: T,
+    pub bottom: T,
+    pub left: T,
+}
+
+impl<T: Copy> Edges<T> {
+    /// The same on every side.
+    pub fn uniform(value: T) -> Self {
+        Edges {
+            top: value,
+            right: value,
+            bottom: value,
+            left: value,
+        }
+    }
+
+    /// The side named `top`, `right`, `bottom` or `left`.
+    pub fn side_mut(&mut self, side: &str) -> Option<&mut T> {
+        match side {
+            "top" => Some(&mut self.top),
+            "right" => Some(&mut self.right),
+            "bottom" => Some(&mut self.bottom),
+            "left" => Some(&mut self.left),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Copy + std::ops::Add<Output = T>> Edges<T> {
+    /// Left plus right.
+    pub fn horizontal(&self) -> T {
+        self.left + self.right
+    }
+
+    /// Top plus bottom.
+    pub fn vertical(&self) -> T {
+        self.top + self.bottom
+    }
+}
+
+/// The margin, border and padding around the content of a box, in pixels.
+///
+/// Positions and sizes in layout are of border boxes, as drawn; the margins
+/// are outside them and the content is inside the border and padding.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct BoxEdges {
+    pub margin: Edges<i32>,
+    pub border: Edges<u32>,
+    pub padding: Edges<u32>,
+}
+
+impl BoxEdges {
+    /// The border and padding on the left and right.
+    pub fn horizontal(&self) -> u32 {
+        self.border.horizontal() + self.padding.horizontal()
+    }
+
+    /// The border and padding at the top and bottom.
+    pub fn vertical(&self) -> u32 {
+        self.border.vertical() + self.padding.vertical()
+    }
+
+    /// Where the content box starts, relative to the border box.
+    pub fn content_offset(&self) -> Point {
+        Point {
+            x: (self.border.left + self.padding.left) as i32,
+            y: (self.border.top + self.padding.top) as i32,
+        }
+    }
+
+    /// The size of the border box around content of size `content`.
+    pub fn border_box(&self, content: Size) -> Size {
+        Size {
+            width: content.width + self.horizontal(),
+            height: content.height + self.vertical(),
+        }
+    }
+
+    /// The content box inside the border box `border_box`.
+    pub fn content_region(&self, border_box: Region) -> Region {
+        let offset = self.content_offset();
+        Region {
+            pos: Point {
+                x: border_box.pos.x + offset.x,
+                y: border_box.pos.y + offset.y,
+            },
+            size: Size {
+                width: border_box.size.width.saturating_sub(self.horizontal()),
+                height: border_box.size.height.saturating_sub(self.vertical()),
+            },
+        }
+    }
+
+    /// The border box `border_box` with its margins around it.
+    pub fn margin_region(&self, border_box: Region) -> Region {
+        let margin = self.margin;
+        Region {
+            pos: Point {
+                x: border_box.pos.x - margin.left,
+                y: border_box.pos.y - margin.top,
+            },
+            size: Size {
+                width: (border_box.size.width as i32 + margin.horizontal()).max(0) as u32,
+                height: (border_box.size.height as i32 + margin.vertical()).max(0) as u32,
+            },
+        }
+    }
+}
+
+/// A size together with the limits it may be stretched between.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Resizable {
+    pub size: Size,
+    pub min_size: Size,
+    pub max_size: Size,
+    pub expand_h: bool,
+    pub expand_v: bool,
+}
+
+impl Resizable {
+    pub fn new() -> Self {
+        Resizable {
+            size: Size::new(),
+            min_size: Size {
+                width: 0,
+                height: 0,
+            },
+            max_size: Size {
+                width: 0,
+                height: 0,
+            },
+            expand_h: false,
+            expand_v: false,
+        }
+    }
+}
+
+/// The direction a sizer lays out its children in.
+pub enum Orient {
+    H,
+    V,
+}
+
+/// What a `Block` holds.
+pub enum BlockData {
+    Space,
+    Sizer { orient: Orient },
+    Text { text: String },
+}
+
+pub type Handle = Rc<Block>;
+pub type WeakHandle = Weak<Block>;
+
+/// A node of the layout tree.
+pub struct Block {
+    pub parent: Cell<Option<WeakHandle>>,
+    pub children: RefCell<Vec<Handle>>,
+    pub data: BlockData,
+    /// The top-left of the border box.
+    pub pos: Point,
+    /// The size of the border box.
+    pub size: Resizable,
+    pub edges: Cell<BoxEdges>,
+}
+
+impl Block {
+    pub fn new_from(data: BlockData) -> Self {
+        Block {
+            parent: Cell::new(None),
+            children: RefCell::new(vec![]),
+            data,
+            pos: Point::new(),
+            size: Resizable::new(),
+            edges: Cell::new(BoxEdges::default()),
+        }
+    }
+
+    /// The border box of the block.
+    pub fn border_region(&self) -> Region {
+        Region {
+            pos: self.pos,
+            size: self.size.size,
+        }
+    }
+
+    /// Where the block's content goes, inside its border and padding.
+    pub fn content_region(&self) -> Region {
+        self.edges.get().content_region(self.border_region())
+    }
+}
+
+/// Something that can tell how large a piece of text is when drawn.
+pub trait DeviceContext {
+    fn measure_text(&self, text: &str, style: &TextStyle) -> Size;
+
+    /// Measures runs drawn one after another in an element styled `style`,
+    /// breaking lines at `\n`.
+    ///
+    /// Each run is measured in `style` changed by its own `RunStyle`. Each
+    /// line is as wide as its pieces together and as tall as the tallest of
+    /// them.
+    fn measure_runs(&self, runs: &[TextRun], style: &TextStyle) -> Size {
+        let mut lines = vec![Size::new()];
+        for run in runs {
+            let run_style = style.with_run(&run.style);
+            for (i, piece) in run.text.split('\n').enumerate() {
+                if i > 0 {
+                    lines.push(Size::new());
+                }
+                let size = self.measure_text(piece, &run_style);
+                let line = lines.last_mut().unwrap();
+                line.width += size.width;
+                line.height = line.height.max(size.height);
+            }
+        }
+
+        Size {
+            width: lines.iter().map(|line| line.width).max().unwrap_or(0),
+            height: lines.iter().map(|line| line.height).sum(),
+        }
+    }
+}
+
+/// A `DeviceContext` with square glyphs 1.25 times the font size (20x20
+/// pixels at the default 16px), for deterministic tests.
+///
+/// `letter-spacing` is added after each glyph and `line-height` replaces the
+/// glyph height; the family, weight and style do not matter.
+#[derive(Default)]
+pub struct TestDC {}
+
+impl TestDC {
+    pub fn new() -> Self {
+        TestDC {}
+    }
+
+    /// The width and height of one glyph in `style`.
+    pub fn glyph_size(style: &TextStyle) -> f32 {
+        style.size * 1.25
+    }
+}
+
+impl DeviceContext for TestDC {
+    fn measure_text(&self, text: &str, style: &TextStyle) -> Size {
+        let lines: Vec<&str> = text.split('\n').collect();
+        let max_len = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        let glyph = TestDC::glyph_size(style);
+        let line_height = style.line_height.unwrap_or(glyph);
+        Size {
+            width: ((glyph + style.letter_spacing).max(0.0) * max_len as f32).round() as u32,
+            height: (line_height * lines.len() as f32).round() as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measure_text() {
+        let dc = TestDC::new();
+        let style = TextStyle::default();
+        let size = dc.measure_text("ab\nc", &style);
+        assert_eq!(
+            size,
+            Size {
+                width: 40,
+                height: 40,
+            }
+        );
+
+        let mut bold = TextRun::plain("b\nc");
+        bold.style.bold = true;
+        assert_eq!(dc.measure_runs(&[TextRun::plain("a"), bold], &style), size);
+        assert_eq!(dc.measure_runs(&[], &style), Size::new());
+
+        // Glyphs scale with the font size; runs can change it.
+        let title = TextStyle {
+            size: 20.0,
+            letter_spacing: 1.0,
+            line_height: Some(30.0),
+            ..TextStyle::default()
+        };
+        assert_eq!(
+            dc.measure_text("ab\nc", &title),
+            Size {
+                width: 52,
+                height: 60,
+            }
+        );
+        let mut small = TextRun::plain("cd");
+        small.style.font_size = Some(8);
+        assert_eq!(
+            dc.measure_runs(&[TextRun::plain("ab"), small], &style),
+            Size {
+                width: 60,
+                height: 20,
+            }
+        );
+    }
+
+    #[test]
+    fn box_edges() {
+        let edges = BoxEdges {
+            margin: Edges {
+                top: 4,
+                right: 0,
+                bottom: -2,
+                left: 8,
+            },
+            border: Edges::uniform(1),
+            padding: Edges {
+                top: 2,
+                right: 3,
+                bottom: 2,
+                left: 3,
+            },
+        };
+        let border_box = Region {
+            pos: Point { x: 10, y: 10 },
+            size: edges.border_box(Size {
+                width: 40,
+                height: 20,
+            }),
+        };
+        assert_eq!(
+            border_box.size,
+            Size {
+                width: 48,
+                height: 26
+            }
+        );
+        assert_eq!(
+            edges.content_region(border_box),
+            Region {
+                pos: Point { x: 14, y: 13 },
+                size: Size {
+                    width: 40,
+                    height: 20
+                },
+            }
+        );
+        assert_eq!(
+            edges.margin_region(border_box),
+            Region {
+                pos: Point { x: 2, y: 6 },
+                size: Size {
+                    width: 56,
+                    height: 28
+                },
+            }
+        );
+    }
+}
@@ -0,0 +1,35 @@
+//! Parsing, cleaning and laying out Wikipedia articles.
+//!
+//! The usual entry point is [`Pipeline`], which turns an HTML string into a
+//! cleaned [`Document`] whose tables can then be laid out.
+
+pub mod bibliography;
+pub mod cache;
+pub mod cascade;
+pub mod charset;
+pub mod content;
+pub mod crawl;
+pub mod document;
+pub mod dom;
+pub mod export;
+pub mod infobox;
+pub mod layout;
+pub mod links;
+pub mod mediawiki;
+pub mod passes;
+pub mod references;
+pub mod section;
+pub mod select;
+pub mod source;
+pub mod spans;
+pub mod style;
+pub mod table;
+pub mod text;
+pub mod traverse;
+pub mod whitespace;
+
+pub use document::{Document, Pipeline};
+pub use layout::{Block, DeviceContext, Point, Size, TestDC};
+pub use style::{BlockProps, Length};
+pub use table::{Table, TableCell};
+pub use text::TextBlock;
@@ -0,0 +1,90 @@
+//! Links that survive the flattening of `<a>` elements.
+//!
+//! When a cleanup pass unwraps an `<a href>`, each text node inside it gets a
+//! [`LinkSpan`] covering the link's text. The spans follow the text through
+//! merging and white space processing, so after `Pipeline::parse` they are
+//! byte ranges into the final text nodes.
+
+use crate::dom::{get_attr, get_elem_name};
+use crate::spans::{Span, SpanTable};
+use html5ever::rcdom::Handle;
+use serde::Serialize;
+
+/// The attributes of an `<a>` element that are worth keeping.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Link {
+    pub href: String,
+    pub title: Option<String>,
+    /// Such as `new` (missing article), `extiw` (other wiki) or `external text`.
+    pub class: Option<String>,
+}
+
+impl Link {
+    /// The link of an `<a>` element with an `href`.
+    pub fn from_element(node: &Handle) -> Option<Self> {
+        if get_elem_name(node) != "a" {
+            return None;
+        }
+
+        Some(Link {
+            href: get_attr(node, "href")?,
+            title: get_attr(node, "title"),
+            class: get_attr(node, "class"),
+        })
+    }
+}
+
+/// A link over a byte range of one text node.
+pub type LinkSpan = Span<Link>;
+
+/// The link spans of the text nodes of one document.
+pub type LinkTable = SpanTable<Link>;
+
+#[cfg(test)]
+mod tests {
+    use crate::Pipeline;
+    use html5ever::rcdom::NodeData;
+
+    #[test]
+    fn links_survive_cleanup() {
+        let doc = Pipeline::new().parse(
+            "<p>1577年、<a href=\"/wiki/%E3%83%9E%E3%82%BC%E3%83%A9%E3%83%B3\" title=\"マゼラン\">マゼラン</a>以来の \
+             <b>世界 <a href=\"/wiki/X\" class=\"new\" title=\"X\">周航</a></b>に\n  \
+             <a rel=\"nofollow\" class=\"external text\" href=\"https://example.org/\">出発</a>。</p>",
+        );
+        let root = doc.root().unwrap();
+        let links = doc.links().in_document(&root);
+        assert_eq!(links.len(), 1);
+
+        let (text, spans) = &links[0];
+        let text = match text.data {
+            NodeData::Text { ref contents } => contents.borrow().to_string(),
+            _ => unreachable!(),
+        };
+        assert_eq!(text, "1577年、マゼラン以来の 世界 周航に出発。");
+
+        let linked: Vec<(&str, &str, Option<&str>)> = spans
+            .iter()
+            .map(|span| {
+                (
+                    &text[span.range.clone()],
+                    span.value.href.as_str(),
+                    span.value.class.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            linked,
+            vec![
+                (
+                    "マゼラン",
+                    "/wiki/%E3%83%9E%E3%82%BC%E3%83%A9%E3%83%B3",
+                    None
+                ),
+                ("周航", "/wiki/X", Some("new")),
+                ("出発", "https://example.org/", Some("external text")),
+            ]
+        );
+        assert_eq!(spans[0].value.title.as_deref(), Some("マゼラン"));
+    }
+}
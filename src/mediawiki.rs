@@ -0,0 +1,296 @@
+//! A client for the MediaWiki Action and REST APIs.
+//!
+//! Metadata (revision, timestamp, categories, redirects) comes from
+//! `api.php?action=query`, and the article body is the Parsoid HTML from
+//! `/api/rest_v1/page/html`, which has none of the skin chrome.
+
+use crate::document::{Document, Pipeline};
+use serde::{Deserialize, Serialize};
+
+/// A title that was redirected or normalized on the way to the article.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Redirect {
+    pub from: String,
+    pub to: String,
+}
+
+/// Everything the Action API tells us about a page.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PageInfo {
+    pub title: String,
+    pub page_id: u64,
+    pub revision: u64,
+    pub timestamp: String,
+    pub categories: Vec<String>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// An article at a specific revision, with its Parsoid HTML.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Article {
+    pub lang: String,
+    #[serde(flatten)]
+    pub info: PageInfo,
+    pub html: String,
+}
+
+impl Article {
+    /// Runs the article HTML through `pipeline`.
+    pub fn parse_with(&self, pipeline: &Pipeline) -> Document {
+        pipeline.parse(&self.html)
+    }
+
+    pub fn parse(&self) -> Document {
+        self.parse_with(&Pipeline::new())
+    }
+}
+
+#[derive(Deserialize)]
+struct QueryResponse {
+    query: Query,
+}
+
+#[derive(Deserialize)]
+struct Query {
+    #[serde(default)]
+    normalized: Vec<Redirect>,
+    #[serde(default)]
+    redirects: Vec<Redirect>,
+    pages: Vec<QueryPage>,
+}
+
+#[derive(Deserialize)]
+struct QueryPage {
+    title: String,
+    #[serde(default)]
+    pageid: Option<u64>,
+    #[serde(default)]
+    missing: bool,
+    #[serde(default)]
+    revisions: Vec<QueryRevision>,
+    #[serde(default)]
+    categories: Vec<QueryCategory>,
+}
+
+#[derive(Deserialize)]
+struct QueryRevision {
+    revid: u64,
+    timestamp: String,
+}
+
+#[derive(Deserialize)]
+struct QueryCategory {
+    title: String,
+}
+
+impl PageInfo {
+    /// Reads the response of the query built by `Client::query_url`
+    /// (`formatversion=2`).
+    pub fn from_query_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let response: QueryResponse = serde_json::from_str(json)?;
+        let query = response.query;
+
+        let page = query
+            .pages
+            .into_iter()
+            .next()
+            .ok_or("no page in response")?;
+        if page.missing {
+            return Err(format!("{} does not exist", page.title).into());
+        }
+        let revision = page
+            .revisions
+            .into_iter()
+            .next()
+            .ok_or("no revision in response")?;
+
+        let mut redirects = query.normalized;
+        redirects.extend(query.redirects);
+
+        Ok(PageInfo {
+            title: page.title,
+            page_id: page.pageid.unwrap_or(0),
+            revision: revision.revid,
+            timestamp: revision.timestamp,
+            categories: page.categories.into_iter().map(|c| c.title).collect(),
+            redirects,
+        })
+    }
+}
+
+/// Percent-encodes a page title for use in a URL path, with spaces as `_`.
+pub fn encode_title(title: &str) -> String {
+    let mut path = String::new();
+    for b in title.trim().replace(' ', "_").bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => path.push(b as char),
+            b'-' | b'_' | b'.' | b'~' | b':' | b'(' | b')' | b',' => path.push(b as char),
+            _ => path.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    path
+}
+
+/// Reverses `encode_title`: decodes percent escapes and turns `_` into spaces.
+pub fn decode_title(path: &str) -> String {
+    percent_decode(&path.replace('_', " "))
+}
+
+/// Decodes the `%XX` escapes in `s`; anything else is left as it is.
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The `/wiki/` URL of `title` on `base_url`, e.g. `https://ja.wikipedia.org`.
+pub fn page_url(base_url: &str, title: &str) -> String {
+    format!("{}/wiki/{}", base_url, encode_title(title))
+}
+
+/// Talks to one language edition of Wikipedia.
+#[derive(Clone, Debug)]
+pub struct Client {
+    lang: String,
+    base_url: String,
+    #[cfg(feature = "fetch")]
+    http: reqwest::Client,
+}
+
+impl Client {
+    pub fn new(lang: &str) -> Self {
+        Client {
+            lang: lang.to_string(),
+            base_url: format!("https://{}.wikipedia.org", lang),
+            #[cfg(feature = "fetch")]
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Points the client at another server, such as a local test double.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn lang(&self) -> &str {
+        &self.lang
+    }
+
+    pub fn query_url(&self, title: &str) -> String {
+        format!(
+            "{}/w/api.php?action=query&format=json&formatversion=2&redirects=1\
+             &prop=revisions%7Ccategories&rvprop=ids%7Ctimestamp&cllimit=max&titles={}",
+            self.base_url,
+            encode_title(title)
+        )
+    }
+
+    pub fn html_url(&self, title: &str, revision: u64) -> String {
+        format!(
+            "{}/api/rest_v1/page/html/{}/{}",
+            self.base_url,
+            encode_title(title),
+            revision
+        )
+    }
+
+    #[cfg(feature = "fetch")]
+    pub async fn page_info(&self, title: &str) -> Result<PageInfo, Box<dyn std::error::Error>> {
+        let resp = self
+            .http
+            .get(&self.query_url(title))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        PageInfo::from_query_json(&resp.text().await?)
+    }
+
+    /// Fetches the metadata of `title`, following redirects, and the Parsoid
+    /// HTML of its current revision.
+    #[cfg(feature = "fetch")]
+    pub async fn article(&self, title: &str) -> Result<Article, Box<dyn std::error::Error>> {
+        let info = self.page_info(title).await?;
+        let resp = self
+            .http
+            .get(&self.html_url(&info.title, info.revision))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(Article {
+            lang: self.lang.clone(),
+            html: resp.text().await?,
+            info,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_info_from_query() {
+        let json = r#"{
+            "batchcomplete": true,
+            "query": {
+                "normalized": [{"fromencoded": false, "from": "ドレーク", "to": "ドレーク"}],
+                "redirects": [{"from": "ドレーク", "to": "フランシス・ドレーク"}],
+                "pages": [{
+                    "pageid": 64578,
+                    "ns": 0,
+                    "title": "フランシス・ドレーク",
+                    "revisions": [{"revid": 81234567, "parentid": 81234000, "timestamp": "2021-01-28T12:00:00Z"}],
+                    "categories": [
+                        {"ns": 14, "title": "Category:イングランドの海軍軍人"},
+                        {"ns": 14, "title": "Category:世界一周した人物"}
+                    ]
+                }]
+            }
+        }"#;
+
+        let info = PageInfo::from_query_json(json).unwrap();
+        assert_eq!(info.title, "フランシス・ドレーク");
+        assert_eq!(info.page_id, 64578);
+        assert_eq!(info.revision, 81234567);
+        assert_eq!(info.timestamp, "2021-01-28T12:00:00Z");
+        assert_eq!(info.categories.len(), 2);
+        assert_eq!(info.redirects[1].to, "フランシス・ドレーク");
+
+        let missing = r#"{"query": {"pages": [{"ns": 0, "title": "X", "missing": true}]}}"#;
+        assert!(PageInfo::from_query_json(missing).is_err());
+    }
+
+    #[test]
+    fn urls() {
+        let client = Client::new("ja").with_base_url("http://localhost:8080/");
+        assert_eq!(
+            client.html_url("Francis Drake", 42),
+            "http://localhost:8080/api/rest_v1/page/html/Francis_Drake/42"
+        );
+        assert!(client.query_url("A/B").ends_with("&titles=A%2FB"));
+
+        let title = "フランシス・ドレーク (A/B)";
+        assert_eq!(decode_title(&encode_title(title)), title);
+        assert_eq!(decode_title("100%_sure"), "100% sure");
+    }
+}
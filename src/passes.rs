@@ -0,0 +1,475 @@
+//! Configurable DOM cleanup passes.
+//!
+//! A [`PassPipeline`] runs a list of [`DomPass`]es over a document. The usual
+//! one is a [`CleanupPass`], whose rules pick elements with a
+//! [`select::Selector`](crate::select::Selector) and unwrap, remove, rename
+//! or keep them, followed by [`MergeText`]. Pipelines can be read from a
+//! config file, where each word after the action is a selector (so
+//! combinators are written without spaces, as in `div>p`):
+//!
+//! ```text
+//! # action  selectors...
+//! keep      a.external
+//! unwrap    a b i sup cite span
+//! remove    .mw-editsection sup.reference [role=navigation]
+//! remove    #toc                  # a lone `#` starts a comment
+//! rename    center div
+//! ```
+//!
+//! The first rule matching an element decides what happens to it, so `keep`
+//! lines go before the rules they make exceptions to.
+
+use crate::cascade::ComputedStyles;
+use crate::dom::{self, concatenate_text};
+use crate::links::{Link, LinkTable};
+use crate::select::{Selector, SelectorError};
+use crate::text::{RunStyle, StyleTable};
+use html5ever::rcdom::{Handle, Node, NodeData};
+use html5ever::{LocalName, QualName};
+use std::cell::RefCell;
+use std::fmt::{Debug, Display, Formatter};
+use std::fs;
+use std::rc::Rc;
+
+/// The behaviour of `dom::remove_decoration`.
+///
+/// Footnote anchors left by `references::extract` are kept, and so are the
+/// spans `section::Section::outline` reads headings with: the headline that
+/// holds a section's anchor id and the edit link left out of its title.
+pub const DEFAULT_CONFIG: &str =
+    "keep a.footnote span.mw-headline span.mw-editsection\nunwrap a b i sup cite span\n";
+
+/// What passes learn about a document while transforming it.
+#[derive(Default)]
+pub struct PassContext {
+    /// Links whose `<a>` elements were unwrapped.
+    pub links: LinkTable,
+    /// Styles of unwrapped inline elements such as `<b>` or `<span lang>`.
+    pub styles: StyleTable,
+    /// The computed styles of the document before any pass ran, which the
+    /// styles of unwrapped elements are read from.
+    pub computed: ComputedStyles,
+}
+
+/// One transformation of a document.
+pub trait DomPass {
+    fn name(&self) -> &str;
+
+    /// Transforms the descendants of `root`.
+    fn run(&self, root: &Handle, ctx: &mut PassContext);
+}
+
+/// What a `CleanupPass` does with a matching element.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// Replace the element by its children.
+    Unwrap,
+    /// Drop the element and everything in it.
+    Remove,
+    /// Change the tag name, keeping attributes and children.
+    Rename(String),
+    /// Leave the element alone (its descendants are still cleaned).
+    Keep,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub selector: Selector,
+    pub action: Action,
+}
+
+/// Applies the first matching rule to every element below the root.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CleanupPass {
+    pub rules: Vec<Rule>,
+}
+
+impl CleanupPass {
+    pub fn rule(mut self, selector: &str, action: Action) -> Result<Self, SelectorError> {
+        self.rules.push(Rule {
+            selector: Selector::parse(selector)?,
+            action,
+        });
+        Ok(self)
+    }
+
+    fn action_for(&self, node: &Handle) -> Option<&Action> {
+        self.rules
+            .iter()
+            .find(|rule| rule.selector.matches(node))
+            .map(|rule| &rule.action)
+    }
+
+    fn clean(&self, node: &Handle, ctx: &mut PassContext) {
+        let mut i = 0;
+        while i < node.children.borrow().len() {
+            let child = node.children.borrow()[i].clone();
+
+            match self.action_for(&child) {
+                Some(Action::Unwrap) => {
+                    if let Some(link) = Link::from_element(&child) {
+                        ctx.links.annotate(&child, &link);
+                    }
+                    if let Some(style) = RunStyle::from_element(&child, &ctx.computed) {
+                        ctx.styles.annotate(&child, &style);
+                    }
+
+                    // The grandchildren take the child's place and are looked at next.
+                    dom::unwrap_element(&child);
+                }
+                Some(Action::Remove) => dom::detach(&child),
+                Some(Action::Rename(new_name)) => {
+                    let renamed = rename(&child, new_name);
+                    self.clean(&renamed, ctx);
+                    i += 1;
+                }
+                Some(Action::Keep) | None => {
+                    self.clean(&child, ctx);
+                    i += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Puts a copy of the element `node` named `new_name` in its place, with
+/// `node`'s children moved into it, and returns the copy.
+fn rename(node: &Handle, new_name: &str) -> Handle {
+    let (name, attrs) = match node.data {
+        NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } => (name, attrs),
+        _ => return node.clone(),
+    };
+
+    let renamed = Node::new(NodeData::Element {
+        name: QualName::new(
+            name.prefix.clone(),
+            name.ns.clone(),
+            LocalName::from(new_name),
+        ),
+        attrs: RefCell::new(attrs.borrow().clone()),
+        template_contents: None,
+        mathml_annotation_xml_integration_point: false,
+    });
+
+    dom::move_children(node, &renamed);
+    dom::replace(node, &renamed);
+
+    renamed
+}
+
+impl DomPass for CleanupPass {
+    fn name(&self) -> &str {
+        "cleanup"
+    }
+
+    fn run(&self, root: &Handle, ctx: &mut PassContext) {
+        self.clean(root, ctx);
+    }
+}
+
+/// Merges adjacent text nodes everywhere below the root, moving their link
+/// and style spans along.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MergeText;
+
+impl DomPass for MergeText {
+    fn name(&self) -> &str {
+        "merge-text"
+    }
+
+    fn run(&self, root: &Handle, ctx: &mut PassContext) {
+        if !ctx.links.is_empty() || !ctx.styles.is_empty() {
+            // The first text node of a run is the one the others are appended to.
+            let mut run: Option<(Handle, usize)> = None;
+            for child in root.children.borrow().iter() {
+                match child.data {
+                    NodeData::Text { ref contents } => {
+                        let len = contents.borrow().len();
+                        match run {
+                            Some((ref first, ref mut offset)) => {
+                                ctx.links.append(first, child, *offset);
+                                ctx.styles.append(first, child, *offset);
+                                *offset += len;
+                            }
+                            None => run = Some((child.clone(), len)),
+                        }
+                    }
+                    _ => run = None,
+                }
+            }
+        }
+
+        concatenate_text(root);
+        for child in root.children.borrow().iter() {
+            self.run(child, ctx);
+        }
+    }
+}
+
+/// A line of a pass config that could not be understood.
+#[derive(Debug, PartialEq)]
+pub struct ConfigError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// `line` up to its comment. A comment starts with a `#` that begins the
+/// line, or with a `#` between whitespace and whitespace or the end of the
+/// line; other `#`s belong to `#id` selectors or to attribute values such
+/// as `[href="#top"]`.
+fn strip_comment(line: &str) -> &str {
+    if line.trim_start().starts_with('#') {
+        return "";
+    }
+
+    let mut in_brackets = false;
+    let mut after_space = false;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '#' if after_space
+                && !in_brackets
+                && chars.peek().is_none_or(|&(_, next)| next.is_whitespace()) =>
+            {
+                return &line[..i];
+            }
+            '[' => in_brackets = true,
+            ']' => in_brackets = false,
+            _ => {}
+        }
+        after_space = c.is_whitespace();
+    }
+    line
+}
+
+/// An ordered list of passes.
+#[derive(Clone)]
+pub struct PassPipeline {
+    passes: Vec<Rc<dyn DomPass>>,
+}
+
+impl Default for PassPipeline {
+    /// Unwraps `a, b, i, sup, cite, span` and merges text, like
+    /// `dom::remove_decoration`.
+    fn default() -> Self {
+        PassPipeline::from_config(DEFAULT_CONFIG).unwrap()
+    }
+}
+
+impl Debug for PassPipeline {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.passes.iter().map(|pass| pass.name()))
+            .finish()
+    }
+}
+
+impl PassPipeline {
+    /// A pipeline that does nothing.
+    pub fn empty() -> Self {
+        PassPipeline { passes: vec![] }
+    }
+
+    pub fn pass<P: DomPass + 'static>(mut self, pass: P) -> Self {
+        self.passes.push(Rc::new(pass));
+        self
+    }
+
+    /// Reads the rules of a `CleanupPass` (see the module documentation) and
+    /// follows it with `MergeText`.
+    pub fn from_config(config: &str) -> Result<Self, ConfigError> {
+        let mut cleanup = CleanupPass::default();
+
+        for (i, line) in config.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            let error = |message: String| ConfigError {
+                line: i + 1,
+                message,
+            };
+            let selector_error =
+                |selector: &str, e: SelectorError| error(format!("`{}`: {}", selector, e));
+
+            let mut words = line.split_whitespace();
+            let action = match words.next() {
+                Some(action) => action,
+                None => continue,
+            };
+            let args: Vec<&str> = words.collect();
+
+            if action == "rename" {
+                if args.len() != 2 {
+                    return Err(error(
+                        "`rename` takes a selector and a tag name".to_string(),
+                    ));
+                }
+                cleanup = cleanup
+                    .rule(args[0], Action::Rename(args[1].to_ascii_lowercase()))
+                    .map_err(|e| selector_error(args[0], e))?;
+                continue;
+            }
+
+            let action = match action {
+                "unwrap" => Action::Unwrap,
+                "remove" => Action::Remove,
+                "keep" => Action::Keep,
+                _ => return Err(error(format!("unknown action `{}`", action))),
+            };
+            if args.is_empty() {
+                return Err(error("expected at least one selector".to_string()));
+            }
+            for selector in args {
+                cleanup = cleanup
+                    .rule(selector, action.clone())
+                    .map_err(|e| selector_error(selector, e))?;
+            }
+        }
+
+        Ok(PassPipeline::empty().pass(cleanup).pass(MergeText))
+    }
+
+    /// Reads a config file; see `from_config`.
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let config = fs::read_to_string(path)?;
+        Ok(PassPipeline::from_config(&config)?)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    pub fn run(&self, root: &Handle) -> PassContext {
+        let mut ctx = PassContext::default();
+        self.run_in(root, &mut ctx);
+        ctx
+    }
+
+    /// Runs the passes with what `ctx` already knows of the document, such
+    /// as its computed styles.
+    pub fn run_in(&self, root: &Handle, ctx: &mut PassContext) {
+        for pass in self.passes.iter() {
+            pass.run(root, ctx);
+            debug_assert_eq!(dom::check_parents(root), Ok(()), "after {}", pass.name());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::{collect_text, document_element, find_elements, parse};
+    use html5ever::serialize::{serialize, SerializeOpts};
+
+    fn body_html(html_data: &str, pipeline: &PassPipeline) -> String {
+        let dom = parse(html_data);
+        let root = document_element(&dom).unwrap();
+        pipeline.run(&root);
+
+        let body = find_elements(&root, "body")[0].clone();
+        let mut bytes = vec![];
+        serialize(&mut bytes, &body, SerializeOpts::default()).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn selectors() {
+        let config = r#"
+            keep    div.infobox>p span[lang|=es]
+            remove  sup.reference.x[id][data-n="1"] li:not(:first-child)
+            unwrap  span
+        "#;
+        let pipeline = PassPipeline::from_config(config).unwrap();
+
+        let html_data = r#"<div class="infobox"><p><span>A</span></p></div><p><span lang="es-ES">B</span><span>C</span><sup class="reference x" id="r" data-n="1">[1]</sup><sup class="reference">[2]</sup></p><ul><li>1</li><li>2</li></ul>"#;
+        let out = body_html(html_data, &pipeline);
+        assert_eq!(
+            out,
+            r#"<div class="infobox"><p>A</p></div><p><span lang="es-ES">B</span>C<sup class="reference">[2]</sup></p><ul><li>1</li></ul>"#
+        );
+
+        assert_eq!(
+            PassPipeline::from_config("keep a\nremove p..x").unwrap_err(),
+            ConfigError {
+                line: 2,
+                message: "`p..x`: column 2: expected a class name after `.`".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn default_pipeline() {
+        let html_data = r#"<p>エル・ドラケ（<span lang="es"><i>El <b>Draque</b></i></span>）<a href="/wiki/X">X</a></p>"#;
+        let out = body_html(html_data, &PassPipeline::default());
+        assert_eq!(out, "<p>エル・ドラケ（El Draque）X</p>");
+    }
+
+    #[test]
+    fn configured_pipeline() {
+        let config = "
+            # links to other articles stay
+            keep    a.internal
+            remove  .mw-editsection sup.reference
+            unwrap  a b span
+            rename  center div
+        ";
+        let pipeline = PassPipeline::from_config(config).unwrap();
+        assert_eq!(pipeline.names(), vec!["cleanup", "merge-text"]);
+
+        let html_data = r#"<center><h2>見出し<span class="mw-editsection">[編集]</span></h2><p><a class="internal" href="/wiki/A"><b>A</b></a>と<a href="/wiki/B">B</a><sup class="reference">[1]</sup></p></center>"#;
+        let out = body_html(html_data, &pipeline);
+        assert_eq!(
+            out,
+            r#"<div><h2>見出し</h2><p><a class="internal" href="/wiki/A">A</a>とB</p></div>"#
+        );
+
+        let dom = parse(html_data);
+        let root = document_element(&dom).unwrap();
+        pipeline.run(&root);
+        assert_eq!(collect_text(&root), "見出しAとB");
+
+        assert_eq!(
+            PassPipeline::from_config("unwrap a\nexplode b").unwrap_err(),
+            ConfigError {
+                line: 2,
+                message: "unknown action `explode`".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn config_comments() {
+        let config = r##"
+# the table of contents and navboxes go
+remove .navbox #toc   # both of them
+keep a[href="#cite_note-1"]#first
+unwrap a
+"##;
+        let pipeline = PassPipeline::from_config(config).unwrap();
+
+        let html_data = r##"<div id="toc">目次</div><div class="navbox">ナビ</div><p><a id="first" href="#cite_note-1">[1]</a><a href="#cite_note-1">[1]</a><a href="/wiki/A">A</a></p>"##;
+        let out = body_html(html_data, &pipeline);
+        assert_eq!(
+            out,
+            r##"<p><a id="first" href="#cite_note-1">[1]</a>[1]A</p>"##
+        );
+
+        assert_eq!(
+            PassPipeline::from_config("remove #toc\nremove # nothing").unwrap_err(),
+            ConfigError {
+                line: 2,
+                message: "expected at least one selector".to_string()
+            }
+        );
+    }
+}
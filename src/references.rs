@@ -0,0 +1,414 @@
+//! Footnotes and the reference lists they point to.
+//!
+//! MediaWiki puts a footnote marker in the text as
+//! `<sup id="cite_ref-1" class="reference"><a href="#cite_note-1">[1]</a></sup>`
+//! and the note itself in an `ol.references` further down, as an
+//! `li#cite_note-1` with back-links to the markers and a
+//! `span.reference-text`. Book and journal citations are a `<cite>`
+//! followed by a `span.Z3988` holding the same data as COinS.
+//!
+//! [`extract`] reads the lists into [`Reference`]s, replaces each marker
+//! with an empty footnote anchor (`<a class="footnote">`) so the marker text
+//! leaves the body, and removes the lists, leaving it to the output to put
+//! the references at the end.
+
+use crate::dom::{self, collect_text, find_elements, get_attr, get_elem_name};
+use crate::mediawiki::percent_decode;
+use crate::select::Selector;
+use crate::traverse;
+use html5ever::rcdom::{Handle, Node, NodeData};
+use html5ever::tendril::StrTendril;
+use html5ever::{Attribute, LocalName, Namespace, QualName};
+use serde::Serialize;
+use std::cell::RefCell;
+
+/// The class of the anchors that take the place of footnote markers.
+pub const FOOTNOTE_CLASS: &str = "footnote";
+
+/// A cited work, from a `<cite>` and its COinS data.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Citation {
+    /// The `id` of the `<cite>`, such as `Reference-Maxine_Snowden-2016`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// `book`, `journal`, `web` and so on, from the `<cite>` classes or the COinS genre.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// The citation as it reads on the page.
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The journal or website an article is in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pages: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub isbn: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+/// One note of a reference list.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Reference {
+    /// The `id` of the list item, such as `cite_note-1`.
+    pub id: String,
+    /// The marker text without brackets, such as `1` or `註釈 1`.
+    pub marker: String,
+    pub text: String,
+    pub citations: Vec<Citation>,
+    /// The ids of the markers that point here, such as `cite_ref-1`.
+    pub back_links: Vec<String>,
+}
+
+/// Reads the reference lists under `root`, replaces the footnote markers
+/// with anchors and removes the lists.
+pub fn extract(root: &Handle) -> Vec<Reference> {
+    let lists = Selector::parse("ol.references").unwrap().select(root);
+    let mut references: Vec<Reference> = vec![];
+    for list in lists.iter() {
+        let items = list
+            .children
+            .borrow()
+            .iter()
+            .filter(|child| get_elem_name(child) == "li")
+            .cloned()
+            .collect::<Vec<_>>();
+        for (n, item) in items.iter().enumerate() {
+            if let Some(reference) = read_item(item, n + 1) {
+                references.push(reference);
+            }
+        }
+    }
+
+    let mut marked: Vec<String> = vec![];
+    for sup in Selector::parse("sup.reference").unwrap().select(root) {
+        let target = find_elements(&sup, "a")
+            .iter()
+            .find_map(|a| get_attr(a, "href"))
+            .and_then(|href| href.split('#').nth(1).map(percent_decode));
+        let target = match target {
+            Some(target) => target,
+            None => continue,
+        };
+
+        let marker = marker_text(&collect_text(&sup));
+        // A note's marker is the text of the first marker pointing to it.
+        if !marked.contains(&target) {
+            if let Some(reference) = references.iter_mut().find(|r| r.id == target) {
+                reference.marker = marker.clone();
+            }
+            marked.push(target.clone());
+        }
+        dom::replace(&sup, &anchor(&sup, &target, &marker));
+    }
+
+    for list in lists.iter() {
+        // Drop the `div.reflist` wrapper too if the list was all it held.
+        let wrapper = traverse::parent(list).filter(|parent| {
+            get_elem_name(parent) == "div"
+                && parent
+                    .children
+                    .borrow()
+                    .iter()
+                    .filter(|child| !get_elem_name(child).is_empty())
+                    .count()
+                    == 1
+        });
+        dom::detach(wrapper.as_ref().unwrap_or(list));
+    }
+
+    references
+}
+
+fn read_item(item: &Handle, number: usize) -> Option<Reference> {
+    let id = get_attr(item, "id")?;
+    let text_span = Selector::parse("span.reference-text")
+        .unwrap()
+        .select(item)
+        .into_iter()
+        .next();
+
+    let back_links = find_elements(item, "a")
+        .iter()
+        .filter_map(|a| get_attr(a, "href"))
+        .filter_map(|href| href.strip_prefix('#').map(percent_decode))
+        .filter(|target| target.starts_with("cite_ref"))
+        .collect();
+
+    let citations = find_elements(item, "cite")
+        .iter()
+        .map(read_citation)
+        .collect();
+
+    Some(Reference {
+        id,
+        marker: number.to_string(),
+        text: collect_text(text_span.as_ref().unwrap_or(item))
+            .trim()
+            .to_string(),
+        citations,
+        back_links,
+    })
+}
+
+fn read_citation(cite: &Handle) -> Citation {
+    let text = collect_text(cite).trim().to_string();
+    let mut citation = Citation {
+        id: get_attr(cite, "id"),
+        kind: get_attr(cite, "class").and_then(|class| {
+            class
+                .split_whitespace()
+                .find(|c| *c != "citation")
+                .map(str::to_string)
+        }),
+        ..Citation::default()
+    };
+
+    if let Some(coins) = next_element(cite)
+        .filter(|span| Selector::parse("span.Z3988").unwrap().matches(span))
+        .and_then(|span| get_attr(&span, "title"))
+    {
+        read_coins(&coins, &mut citation);
+    }
+
+    if citation.title.is_none() {
+        citation.title = find_elements(cite, "i")
+            .first()
+            .map(collect_text)
+            .filter(|title| !title.is_empty());
+    }
+    if citation.date.is_none() {
+        citation.date = find_year(&text);
+    }
+    if citation.isbn.is_none() {
+        citation.isbn = find_isbn(&text);
+    }
+    if citation.url.is_none() {
+        citation.url = find_elements(cite, "a")
+            .iter()
+            .filter_map(|a| get_attr(a, "href"))
+            .find(|href| href.starts_with("http://") || href.starts_with("https://"));
+    }
+
+    citation.text = text;
+    citation
+}
+
+/// Fills in `citation` from a COinS `title`, a query string of `rft.*` keys.
+fn read_coins(coins: &str, citation: &mut Citation) {
+    let mut aulast = None;
+    let mut aufirst = None;
+
+    for pair in coins.split('&') {
+        let mut kv = pair.splitn(2, '=');
+        let (key, value) = match (kv.next(), kv.next()) {
+            (Some(key), Some(value)) => (key, percent_decode(&value.replace('+', " "))),
+            _ => continue,
+        };
+        let value = value.replace("&nbsp;", " ").trim().to_string();
+        if value.is_empty() {
+            continue;
+        }
+
+        match key {
+            "rft.genre" if citation.kind.is_none() => citation.kind = Some(value),
+            "rft.btitle" | "rft.atitle" | "rft.title" => {
+                citation.title.get_or_insert(value);
+            }
+            "rft.jtitle" => citation.container = Some(value),
+            "rft.au" if !citation.authors.contains(&value) => citation.authors.push(value),
+            "rft.aulast" => aulast = Some(value),
+            "rft.aufirst" => aufirst = Some(value),
+            "rft.date" => citation.date = Some(value),
+            "rft.pub" => citation.publisher = Some(value),
+            "rft.pages" => citation.pages = Some(value),
+            "rft.isbn" => citation.isbn = Some(value),
+            "rft_id" if value.starts_with("http") => citation.url = Some(value),
+            _ => {}
+        }
+    }
+
+    if citation.authors.is_empty() {
+        let name = match (aulast, aufirst) {
+            (Some(last), Some(first)) => Some(format!("{}, {}", last, first)),
+            (last, first) => last.or(first),
+        };
+        citation.authors.extend(name);
+    }
+}
+
+/// The first four-digit number that looks like a year.
+fn find_year(text: &str) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .windows(4)
+        .enumerate()
+        .filter(|(i, w)| {
+            w.iter().all(char::is_ascii_digit)
+                && (*i == 0 || !chars[i - 1].is_ascii_digit())
+                && chars.get(i + 4).is_none_or(|c| !c.is_ascii_digit())
+        })
+        .map(|(_, w)| w.iter().collect::<String>())
+        .find(|year| (1000..2100).contains(&year.parse::<u32>().unwrap()))
+}
+
+/// The digits after `ISBN`, with their hyphens.
+fn find_isbn(text: &str) -> Option<String> {
+    let rest = &text[text.find("ISBN")? + 4..];
+    let isbn: String = rest
+        .trim_start_matches(|c: char| c.is_whitespace() || c == ':' || c == '-')
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '-' || *c == 'X')
+        .collect();
+    let isbn = isbn.trim_end_matches('-');
+    if isbn.is_empty() {
+        None
+    } else {
+        Some(isbn.to_string())
+    }
+}
+
+/// `[1]` or `［註釈 1］` without the brackets.
+fn marker_text(text: &str) -> String {
+    text.trim()
+        .trim_start_matches(['[', '［'])
+        .trim_end_matches([']', '］'])
+        .trim()
+        .to_string()
+}
+
+/// An empty `<a class="footnote" id href data-marker>` in place of `marker`.
+fn anchor(marker: &Handle, target: &str, text: &str) -> Handle {
+    let name = match marker.data {
+        NodeData::Element { ref name, .. } => name,
+        _ => unreachable!(),
+    };
+    let attr = |name: &str, value: &str| Attribute {
+        name: QualName::new(None, Namespace::from(""), LocalName::from(name)),
+        value: StrTendril::from(value),
+    };
+
+    let mut attrs = vec![
+        attr("class", FOOTNOTE_CLASS),
+        attr("href", &format!("#{}", target)),
+        attr("data-marker", text),
+    ];
+    if let Some(id) = get_attr(marker, "id") {
+        attrs.insert(0, attr("id", &id));
+    }
+
+    Node::new(NodeData::Element {
+        name: QualName::new(None, name.ns.clone(), LocalName::from("a")),
+        attrs: RefCell::new(attrs),
+        template_contents: None,
+        mathml_annotation_xml_integration_point: false,
+    })
+}
+
+fn next_element(node: &Handle) -> Option<Handle> {
+    traverse::following_siblings(node).find(|sibling| traverse::local_name(sibling).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pipeline;
+
+    const ARTICLE: &str = r##"<div class="mw-parser-output">
+        <p>ドレークは航海者<sup id="cite_ref-1" class="reference"><a href="#cite_note-1">&#91;1&#93;</a></sup>。
+        ナイトに叙された<sup id="cite_ref-4" class="reference"><a href="#cite_note-4">&#91;註釈 1&#93;</a></sup><sup id="cite_ref-スティーヴン(2019)_5-0" class="reference"><a href="#cite_note-%E3%82%B9%E3%83%86%E3%82%A3%E3%83%BC%E3%83%B4%E3%83%B3(2019)-5">&#91;2&#93;</a></sup>。</p>
+        <h2>脚注</h2>
+        <ol class="references">
+            <li id="cite_note-4"><b><a href="#cite_ref-4">^</a></b> <span class="reference-text">紋章の逸話。</span></li>
+        </ol>
+        <h2>出典</h2>
+        <div class="reflist"><ol class="references">
+            <li id="cite_note-1"><b><a href="#cite_ref-1">^</a></b> <span class="reference-text"><cite
+                style="font-style:normal" class="citation book">ボイス・ペンローズ&#32;荒尾克己訳&#32;(1985).&#32;<i>大航海時代</i>.&#32;筑摩書房.&#32;p.&#160;221</cite><span
+                class="Z3988" title="ctx_ver=Z39.88-2004&amp;rft.genre=book&amp;rft.btitle=%E5%A4%A7%E8%88%AA%E6%B5%B7%E6%99%82%E4%BB%A3&amp;rft.aulast=%E3%83%9A%E3%83%B3%E3%83%AD%E3%83%BC%E3%82%BA&amp;rft.au=%E3%83%9C%E3%82%A4%E3%82%B9%E3%83%BB%E3%83%9A%E3%83%B3%E3%83%AD%E3%83%BC%E3%82%BA&amp;rft.date=1985&amp;rft.pages=p.%26nbsp%3B221&amp;rft.pub=%E7%AD%91%E6%91%A9%E6%9B%B8%E6%88%BF"><span
+                style="display: none;">&#160;</span></span></span></li>
+            <li id="cite_note-スティーヴン(2019)-5">^ <a href="#cite_ref-スティーヴン(2019)_5-0"><sup>a</sup></a>
+                <a href="#cite_ref-スティーヴン(2019)_5-1"><sup>b</sup></a> <span class="reference-text"><cite
+                class="book">Maxine Snowden『北極・南極探検の歴史』丸善出版、2016年、17頁。
+                <a href="/wiki/ISBN">ISBN</a>&#160;<a href="/wiki/X">978-4-621-30068-8</a>。</cite></span></li>
+        </ol></div>
+        <p>以上。</p>
+    </div>"##;
+
+    #[test]
+    fn references() {
+        let doc = Pipeline::new().footnotes(true).parse(ARTICLE);
+        let references = doc.references();
+
+        let ids: Vec<(&str, &str)> = references
+            .iter()
+            .map(|r| (r.id.as_str(), r.marker.as_str()))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                ("cite_note-4", "註釈 1"),
+                ("cite_note-1", "1"),
+                ("cite_note-スティーヴン(2019)-5", "2"),
+            ]
+        );
+        assert_eq!(references[0].text, "紋章の逸話。");
+        assert_eq!(
+            references[2].back_links,
+            vec![
+                "cite_ref-スティーヴン(2019)_5-0",
+                "cite_ref-スティーヴン(2019)_5-1"
+            ]
+        );
+
+        let book = &references[1].citations[0];
+        assert_eq!(book.kind.as_deref(), Some("book"));
+        assert_eq!(book.title.as_deref(), Some("大航海時代"));
+        assert_eq!(book.authors, vec!["ボイス・ペンローズ"]);
+        assert_eq!(book.date.as_deref(), Some("1985"));
+        assert_eq!(book.publisher.as_deref(), Some("筑摩書房"));
+        assert_eq!(book.pages.as_deref(), Some("p. 221"));
+
+        let plain = &references[2].citations[0];
+        assert_eq!(plain.kind.as_deref(), Some("book"));
+        assert_eq!(plain.date.as_deref(), Some("2016"));
+        assert_eq!(plain.isbn.as_deref(), Some("978-4-621-30068-8"));
+
+        // The markers and the lists are gone from the text; anchors stand in for the markers.
+        assert_eq!(
+            doc.text(),
+            "ドレークは航海者。ナイトに叙された。 脚注 出典 以上。"
+        );
+        let root = doc.root().unwrap();
+        let anchors = Selector::parse("a.footnote").unwrap().select(&root);
+        assert_eq!(anchors.len(), 3);
+        assert_eq!(
+            get_attr(&anchors[0], "href").as_deref(),
+            Some("#cite_note-1")
+        );
+        assert_eq!(
+            get_attr(&anchors[1], "data-marker").as_deref(),
+            Some("註釈 1")
+        );
+        assert_eq!(
+            get_attr(&anchors[2], "href").as_deref(),
+            Some("#cite_note-スティーヴン(2019)-5")
+        );
+        assert!(find_elements(&root, "ol").is_empty());
+    }
+
+    #[test]
+    fn off_by_default() {
+        let doc = Pipeline::new().parse(ARTICLE);
+        assert!(doc.references().is_empty());
+        assert!(doc.text().starts_with("ドレークは航海者[1]。"));
+    }
+}
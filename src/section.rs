@@ -0,0 +1,349 @@
+//! The section outline of an article, from its headings.
+//!
+//! MediaWiki output is flat: headings and paragraphs are siblings in
+//! `.mw-parser-output`, and a section is a heading plus everything after it
+//! up to the next heading of the same or a higher level. Headings come as a
+//! bare `<h2>` with a `span.mw-headline` holding the anchor id (older
+//! skins), as an `<h2 id>` (Parsoid), or wrapped in `div.mw-heading`.
+
+use crate::cascade::ComputedStyles;
+use crate::dom::{self, collect_text, collect_text_except, find_elements, get_attr, get_elem_name};
+use crate::mediawiki::decode_title;
+use crate::traverse;
+use html5ever::rcdom::Handle;
+use serde::Serialize;
+use std::rc::Rc;
+
+/// Where a section is in the DOM: children `heading..end` of `parent`.
+///
+/// The indices are only good until the children of `parent` change.
+#[derive(Clone, Debug)]
+pub struct SectionRange {
+    pub parent: Handle,
+    /// The index of the heading (or its `div.mw-heading` wrapper).
+    pub heading: usize,
+    /// One past the last node of the section, subsections included.
+    pub end: usize,
+}
+
+/// A heading and what it heads.
+#[derive(Clone, Debug, Serialize)]
+pub struct Section {
+    /// 1 for `h1` through 6 for `h6`.
+    pub level: u8,
+    pub title: String,
+    /// The fragment that links to the section, such as `世界一周の偉業`.
+    pub anchor: String,
+    pub children: Vec<Section>,
+    #[serde(skip)]
+    pub range: SectionRange,
+}
+
+impl Section {
+    /// The top-level sections under `root`.
+    ///
+    /// Sections come from the headings of the element with the most heading
+    /// children (the article body), so headings inside tables or boxes do
+    /// not start sections. Titles follow the `white-space` of the headings
+    /// in `computed`.
+    pub fn outline(root: &Handle, computed: &ComputedStyles) -> Vec<Section> {
+        let mut parents: Vec<(Handle, usize)> = vec![];
+        for heading in traverse::descendants(root) {
+            if heading_level(&heading).is_none() {
+                continue;
+            }
+            if let Some(parent) = traverse::parent(&heading_block(&heading)) {
+                match parents.iter_mut().find(|(p, _)| Rc::ptr_eq(p, &parent)) {
+                    Some((_, count)) => *count += 1,
+                    None => parents.push((parent, 1)),
+                }
+            }
+        }
+
+        // The first of the parents with the most headings.
+        let first = match parents.iter().rev().max_by_key(|(_, count)| *count) {
+            Some((parent, _)) => parent.clone(),
+            None => return vec![],
+        };
+
+        let children = first.children.borrow().clone();
+        let headings: Vec<(usize, u8, Handle)> = children
+            .iter()
+            .enumerate()
+            .filter_map(|(i, child)| heading_in(child).map(|(level, h)| (i, level, h)))
+            .collect();
+
+        let mut flat: Vec<Section> = headings
+            .iter()
+            .enumerate()
+            .map(|(n, (i, level, heading))| {
+                let end = headings[n + 1..]
+                    .iter()
+                    .find(|(_, next_level, _)| next_level <= level)
+                    .map_or(children.len(), |(j, _, _)| *j);
+                // The headline span leaves out the edit link next to it.
+                let headline = headline(heading);
+                let title =
+                    collect_text_except(headline.as_ref().unwrap_or(heading), computed, &|node| {
+                        has_class(node, "mw-editsection")
+                    });
+                let anchor = get_attr(heading, "id")
+                    .or_else(|| headline.and_then(|span| get_attr(&span, "id")))
+                    .unwrap_or_else(|| title.replace(' ', "_"));
+                Section {
+                    level: *level,
+                    anchor,
+                    title,
+                    children: vec![],
+                    range: SectionRange {
+                        parent: first.clone(),
+                        heading: *i,
+                        end,
+                    },
+                }
+            })
+            .collect();
+
+        // Nest each section under the nearest earlier one with a lower level.
+        let mut stack: Vec<Section> = vec![];
+        let mut top: Vec<Section> = vec![];
+        for section in flat.drain(..) {
+            while stack.last().is_some_and(|last| last.level >= section.level) {
+                close(&mut stack, &mut top);
+            }
+            stack.push(section);
+        }
+        while !stack.is_empty() {
+            close(&mut stack, &mut top);
+        }
+
+        top
+    }
+
+    /// Finds the section for a URL fragment, with or without the `#`, in
+    /// percent-encoded, raw or legacy `.E4.B8...` form.
+    pub fn find<'a>(sections: &'a [Section], fragment: &str) -> Option<&'a Section> {
+        let fragment = fragment.trim_start_matches('#');
+        let wanted = [
+            decode_title(fragment),
+            decode_title(&fragment.replace('.', "%")),
+        ];
+
+        sections.iter().find_map(|section| {
+            if wanted.contains(&decode_title(&section.anchor)) {
+                Some(section)
+            } else {
+                Section::find(&section.children, fragment)
+            }
+        })
+    }
+
+    /// The heading and the content of the section, subsections included.
+    pub fn nodes(&self) -> Vec<Handle> {
+        let children = self.range.parent.children.borrow();
+        children[self.range.heading..self.range.end.min(children.len())].to_vec()
+    }
+
+    /// The text of the section without its heading.
+    pub fn text(&self) -> String {
+        self.nodes()
+            .iter()
+            .skip(1)
+            .map(collect_text)
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Detaches everything around the section up to `<body>` (or up to the
+    /// root if there is no `<body>`), so that only the section and the
+    /// elements that contain it are left; `<head>` stays.
+    pub fn isolate(&self) {
+        keep_only(&self.range.parent, self.nodes());
+
+        let mut node = self.range.parent.clone();
+        while get_elem_name(&node) != "body" {
+            let parent = match traverse::parent(&node) {
+                Some(parent) => parent,
+                None => break,
+            };
+            keep_only(&parent, vec![node]);
+            node = parent;
+        }
+    }
+}
+
+/// Detaches the children of `parent` that are not in `nodes`.
+fn keep_only(parent: &Handle, nodes: Vec<Handle>) {
+    let children = parent.children.borrow().clone();
+    for child in children.iter() {
+        if !nodes.iter().any(|node| Rc::ptr_eq(node, child)) {
+            dom::detach(child);
+        }
+    }
+}
+
+fn close(stack: &mut Vec<Section>, top: &mut Vec<Section>) {
+    let section = stack.pop().unwrap();
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(section),
+        None => top.push(section),
+    }
+}
+
+fn heading_level(node: &Handle) -> Option<u8> {
+    match get_elem_name(node).as_str() {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" => Some(4),
+        "h5" => Some(5),
+        "h6" => Some(6),
+        _ => None,
+    }
+}
+
+fn has_class(node: &Handle, name: &str) -> bool {
+    get_attr(node, "class").is_some_and(|class| class.split_whitespace().any(|c| c == name))
+}
+
+fn is_heading_wrapper(node: &Handle) -> bool {
+    get_elem_name(node) == "div" && has_class(node, "mw-heading")
+}
+
+/// The heading itself, or its `div.mw-heading` wrapper.
+fn heading_block(heading: &Handle) -> Handle {
+    match traverse::parent(heading) {
+        Some(parent) if is_heading_wrapper(&parent) => parent,
+        _ => heading.clone(),
+    }
+}
+
+/// The level and heading element of a heading block.
+fn heading_in(block: &Handle) -> Option<(u8, Handle)> {
+    if let Some(level) = heading_level(block) {
+        return Some((level, block.clone()));
+    }
+    if is_heading_wrapper(block) {
+        return block
+            .children
+            .borrow()
+            .iter()
+            .find_map(|child| heading_level(child).map(|level| (level, child.clone())));
+    }
+    None
+}
+
+/// The `span.mw-headline` of an older-style heading.
+fn headline(heading: &Handle) -> Option<Handle> {
+    find_elements(heading, "span")
+        .into_iter()
+        .find(|span| has_class(span, "mw-headline"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::{document_element, parse};
+    use crate::Pipeline;
+
+    const ARTICLE: &str = r#"<div class="mw-parser-output">
+        <p>リード文。</p>
+        <h2><span id=".E7.94.9F.E6.B6.AF"></span><span class="mw-headline" id="生涯">生涯</span><span class="mw-editsection">[編集]</span></h2>
+        <h3><span class="mw-headline" id="生い立ち">生い立ち</span></h3>
+        <p>デヴォンに生まれる。</p>
+        <table><tbody><tr><td><h4>表の中</h4></td></tr></tbody></table>
+        <h3><span class="mw-headline" id="世界一周の偉業">世界一周の偉業</span></h3>
+        <p>1577年に出航。</p>
+        <div class="mw-heading mw-heading2"><h2 id="評価">評価</h2></div>
+        <p>英雄。</p>
+        <h2>脚注 と 出典<span class="mw-editsection">[編集]</span></h2>
+    </div>"#;
+
+    #[test]
+    fn outline() {
+        let dom = parse(ARTICLE);
+        let root = document_element(&dom).unwrap();
+        let sections = Section::outline(&root, &ComputedStyles::default());
+
+        let titles: Vec<(&str, usize)> = sections
+            .iter()
+            .map(|s| (s.title.as_str(), s.children.len()))
+            .collect();
+        assert_eq!(titles, vec![("生涯", 2), ("評価", 0), ("脚注 と 出典", 0)]);
+        assert_eq!(sections[0].children[1].anchor, "世界一周の偉業");
+        assert_eq!(sections[0].children[1].level, 3);
+        assert_eq!(sections[2].anchor, "脚注_と_出典");
+
+        assert_eq!(
+            sections[0].text(),
+            "生い立ち\nデヴォンに生まれる。\n表の中\n世界一周の偉業\n1577年に出航。"
+        );
+
+        let url_fragment = "#%E4%B8%96%E7%95%8C%E4%B8%80%E5%91%A8%E3%81%AE%E5%81%89%E6%A5%AD";
+        let section = Section::find(&sections, url_fragment).unwrap();
+        assert_eq!(section.title, "世界一周の偉業");
+        assert_eq!(
+            Section::find(&sections, ".E8.A9.95.E4.BE.A1")
+                .unwrap()
+                .title,
+            "評価"
+        );
+        assert_eq!(Section::find(&sections, "脚注 と 出典").unwrap().level, 2);
+        assert!(Section::find(&sections, "没後").is_none());
+
+        section.isolate();
+        assert_eq!(collect_text(&root), "世界一周の偉業 1577年に出航。");
+    }
+
+    #[test]
+    fn outline_after_cleanup() {
+        let doc = Pipeline::new().parse(ARTICLE);
+        let sections = doc.sections();
+
+        let headings: Vec<(&str, &str)> = sections
+            .iter()
+            .map(|s| (s.title.as_str(), s.anchor.as_str()))
+            .collect();
+        assert_eq!(
+            headings,
+            [
+                ("生涯", "生涯"),
+                ("評価", "評価"),
+                ("脚注 と 出典", "脚注_と_出典")
+            ]
+        );
+        assert_eq!(sections[0].children[1].anchor, "世界一周の偉業");
+
+        assert!(doc.keep_section("世界一周の偉業"));
+        assert_eq!(doc.text(), "世界一周の偉業 1577年に出航。");
+    }
+
+    #[test]
+    fn isolate_drops_page_chrome() {
+        let page = format!(
+            r#"<html><head><title>t</title></head><body>
+            <div id="mw-navigation"><table><tr><td>メニュー</td></tr></table></div>
+            <div id="content"><h1>フランシス・ドレーク</h1><div id="bodyContent">{}</div></div>
+            <div id="footer">最終更新</div></body></html>"#,
+            ARTICLE
+        );
+        let doc = Pipeline::new().parse(&page);
+        assert_eq!(doc.tables().len(), 2);
+
+        assert!(doc.keep_section("生い立ち"));
+        assert_eq!(doc.text(), "生い立ち デヴォンに生まれる。 表の中");
+        assert_eq!(doc.tables().len(), 1);
+        assert_eq!(doc.select("title").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn white_space_from_styles() {
+        let doc = Pipeline::new().parse(
+            "<style>h2.code { white-space: pre }</style>\
+             <h2 class=code>a  b</h2><p>x</p><h2>c  d</h2><p>y</p>",
+        );
+        let titles: Vec<String> = doc.sections().into_iter().map(|s| s.title).collect();
+        assert_eq!(titles, ["a  b", "c d"]);
+    }
+}
@@ -0,0 +1,555 @@
+//! CSS selectors for querying the DOM.
+//!
+//! [`Selector`] understands type, universal, class, id and attribute
+//! selectors (`[a]`, `=`, `~=`, `|=`, `^=`, `$=`, `*=`), the descendant,
+//! child (`>`), next-sibling (`+`) and subsequent-sibling (`~`) combinators,
+//! selector lists, and the pseudo-classes `:first-child`, `:last-child`,
+//! `:only-child`, `:nth-child()`, `:nth-last-child()` and `:not()`.
+//! `cssparser` does the tokenizing.
+//!
+//! ```
+//! let doc = francis_wb::Pipeline::new()
+//!     .parse("<table class=infobox><tr><th scope=row>渾名</th><td>エル・ドラケ</td></tr></table>");
+//! let th = francis_wb::select::select(&doc.root().unwrap(), "table.infobox th[scope=row]").unwrap();
+//! assert_eq!(th.len(), 1);
+//! ```
+
+use crate::dom::get_attr;
+use crate::traverse;
+use cssparser::{BasicParseErrorKind, ParseError, ParseErrorKind, Parser, ParserInput, Token};
+use html5ever::rcdom::Handle;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+/// A selector that could not be parsed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelectorError {
+    /// Where in the selector, counting from 1.
+    pub column: u32,
+    pub message: String,
+}
+
+impl Display for SelectorError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for SelectorError {}
+
+/// How two compound selectors are related.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Combinator {
+    /// `a b`
+    Descendant,
+    /// `a > b`
+    Child,
+    /// `a + b`
+    NextSibling,
+    /// `a ~ b`
+    SubsequentSibling,
+}
+
+/// How an attribute selector compares values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttrOp {
+    /// `[attr]`
+    Exists,
+    /// `[attr=value]`
+    Equals,
+    /// `[attr~=value]`: one of the space-separated words.
+    Includes,
+    /// `[attr|=value]`: `value` or `value-...`.
+    DashMatch,
+    /// `[attr^=value]`
+    Prefix,
+    /// `[attr$=value]`
+    Suffix,
+    /// `[attr*=value]`
+    Substring,
+}
+
+/// One condition of a compound selector.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Simple {
+    Class(String),
+    Id(String),
+    Attr {
+        name: String,
+        op: AttrOp,
+        value: String,
+    },
+    /// `:nth-child(an+b)`, or `:nth-last-child` counting from the end.
+    NthChild {
+        a: i32,
+        b: i32,
+        from_end: bool,
+    },
+    /// `:only-child`
+    OnlyChild,
+    Not(Vec<Complex>),
+}
+
+/// A run of simple selectors with no combinator between them, such as
+/// `th.title[scope=row]`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Compound {
+    /// `None` matches any element.
+    pub tag: Option<String>,
+    pub conditions: Vec<Simple>,
+}
+
+/// Compound selectors joined by combinators, such as `table.infobox > tr th`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Complex {
+    /// Each compound with the combinator that joins it to the one before;
+    /// the first one's combinator means nothing.
+    pub parts: Vec<(Combinator, Compound)>,
+}
+
+/// A comma-separated list of complex selectors.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Selector {
+    pub alternatives: Vec<Complex>,
+}
+
+type ParseResult<'i, T> = Result<T, ParseError<'i, String>>;
+
+impl Selector {
+    pub fn parse(selector: &str) -> Result<Self, SelectorError> {
+        let mut input = ParserInput::new(selector);
+        let mut parser = Parser::new(&mut input);
+
+        parser
+            .parse_comma_separated(parse_complex)
+            .map(|alternatives| Selector { alternatives })
+            .map_err(|e| SelectorError {
+                column: e.location.column,
+                message: match e.kind {
+                    ParseErrorKind::Custom(message) => message,
+                    ParseErrorKind::Basic(BasicParseErrorKind::UnexpectedToken(token)) => {
+                        format!("unexpected {:?}", token)
+                    }
+                    ParseErrorKind::Basic(BasicParseErrorKind::EndOfInput) => {
+                        "unexpected end of selector".to_string()
+                    }
+                    ParseErrorKind::Basic(kind) => format!("{:?}", kind),
+                },
+            })
+    }
+
+    /// Whether the element `node` matches any of the alternatives.
+    pub fn matches(&self, node: &Handle) -> bool {
+        self.alternatives
+            .iter()
+            .any(|complex| complex.matches(node))
+    }
+
+    /// The descendants of `node` that match, in document order.
+    ///
+    /// Ancestors of `node` count for combinators, as with `querySelectorAll`.
+    pub fn select(&self, node: &Handle) -> Vec<Handle> {
+        let mut found = vec![];
+        for child in node.children.borrow().iter() {
+            self.select_into(child, &mut found);
+        }
+        found
+    }
+
+    fn select_into(&self, node: &Handle, found: &mut Vec<Handle>) {
+        if self.matches(node) {
+            found.push(node.clone());
+        }
+        for child in node.children.borrow().iter() {
+            self.select_into(child, found);
+        }
+    }
+}
+
+/// The descendants of `node` that match `selector`, in document order.
+pub fn select(node: &Handle, selector: &str) -> Result<Vec<Handle>, SelectorError> {
+    Ok(Selector::parse(selector)?.select(node))
+}
+
+/// How specific a selector is: its id, class-like and type selectors.
+/// Greater is more specific.
+pub type Specificity = (u32, u32, u32);
+
+fn add(a: Specificity, b: Specificity) -> Specificity {
+    (a.0 + b.0, a.1 + b.1, a.2 + b.2)
+}
+
+impl Complex {
+    pub fn matches(&self, node: &Handle) -> bool {
+        matches_parts(&self.parts, node)
+    }
+
+    pub fn specificity(&self) -> Specificity {
+        self.parts.iter().fold((0, 0, 0), |sum, (_, compound)| {
+            add(sum, compound.specificity())
+        })
+    }
+}
+
+fn matches_parts(parts: &[(Combinator, Compound)], node: &Handle) -> bool {
+    let ((combinator, compound), rest) = match parts.split_last() {
+        Some(last) => last,
+        None => return false,
+    };
+    if !compound.matches(node) {
+        return false;
+    }
+    if rest.is_empty() {
+        return true;
+    }
+
+    match combinator {
+        Combinator::Child => {
+            parent_element(node).is_some_and(|parent| matches_parts(rest, &parent))
+        }
+        Combinator::Descendant => {
+            let mut ancestor = parent_element(node);
+            while let Some(node) = ancestor {
+                if matches_parts(rest, &node) {
+                    return true;
+                }
+                ancestor = parent_element(&node);
+            }
+            false
+        }
+        Combinator::NextSibling => previous_elements(node)
+            .last()
+            .is_some_and(|sibling| matches_parts(rest, sibling)),
+        Combinator::SubsequentSibling => previous_elements(node)
+            .iter()
+            .any(|sibling| matches_parts(rest, sibling)),
+    }
+}
+
+impl Compound {
+    pub fn matches(&self, node: &Handle) -> bool {
+        let name = match traverse::local_name(node) {
+            Some(name) => name,
+            None => return false,
+        };
+        if self.tag.as_ref().is_some_and(|tag| **tag != **name) {
+            return false;
+        }
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(node))
+    }
+
+    pub fn specificity(&self) -> Specificity {
+        let tag = (0, 0, self.tag.is_some() as u32);
+        self.conditions
+            .iter()
+            .fold(tag, |sum, condition| add(sum, condition.specificity()))
+    }
+}
+
+impl Simple {
+    pub fn matches(&self, node: &Handle) -> bool {
+        match self {
+            Simple::Class(class) => get_attr(node, "class")
+                .is_some_and(|classes| classes.split_whitespace().any(|c| c == class)),
+            Simple::Id(id) => get_attr(node, "id").as_ref() == Some(id),
+            Simple::Attr { name, op, value } => match get_attr(node, name) {
+                Some(actual) => match op {
+                    AttrOp::Exists => true,
+                    AttrOp::Equals => actual == *value,
+                    AttrOp::Includes => actual.split_whitespace().any(|word| word == value),
+                    AttrOp::DashMatch => {
+                        actual == *value || actual.starts_with(&format!("{}-", value))
+                    }
+                    AttrOp::Prefix => !value.is_empty() && actual.starts_with(value.as_str()),
+                    AttrOp::Suffix => !value.is_empty() && actual.ends_with(value.as_str()),
+                    AttrOp::Substring => !value.is_empty() && actual.contains(value.as_str()),
+                },
+                None => false,
+            },
+            Simple::NthChild { a, b, from_end } => {
+                let (index, count) = element_index(node);
+                let position = if *from_end { count - index + 1 } else { index } as i32;
+                // Some n >= 0 with a*n + b == position.
+                match a {
+                    0 => position == *b,
+                    a => (position - b) % a == 0 && (position - b) / a >= 0,
+                }
+            }
+            Simple::OnlyChild => element_index(node).1 == 1,
+            Simple::Not(list) => !list.iter().any(|complex| complex.matches(node)),
+        }
+    }
+
+    /// `:not()` counts as its most specific argument.
+    pub fn specificity(&self) -> Specificity {
+        match self {
+            Simple::Id(_) => (1, 0, 0),
+            Simple::Not(list) => list
+                .iter()
+                .map(Complex::specificity)
+                .max()
+                .unwrap_or((0, 0, 0)),
+            _ => (0, 1, 0),
+        }
+    }
+}
+
+fn parse_complex<'i, 't>(parser: &mut Parser<'i, 't>) -> ParseResult<'i, Complex> {
+    let mut parts: Vec<(Combinator, Compound)> = vec![];
+    let mut current: Option<Compound> = None;
+    // The combinator before the next compound; white space is a descendant
+    // combinator unless `>`, `+` or `~` follows it.
+    let mut combinator: Option<Combinator> = None;
+
+    loop {
+        let location = parser.current_source_location();
+        let token = match parser.next_including_whitespace() {
+            Ok(token) => token.clone(),
+            Err(_) => break,
+        };
+
+        let explicit = match token {
+            Token::WhiteSpace(_) => Some(Combinator::Descendant),
+            Token::Delim('>') => Some(Combinator::Child),
+            Token::Delim('+') => Some(Combinator::NextSibling),
+            Token::Delim('~') => Some(Combinator::SubsequentSibling),
+            _ => None,
+        };
+        if let Some(next) = explicit {
+            if let Some(compound) = current.take() {
+                parts.push((combinator.unwrap_or(Combinator::Descendant), compound));
+                combinator = Some(next);
+            } else if next != Combinator::Descendant {
+                if parts.is_empty() {
+                    return Err(
+                        location.new_custom_error("a selector cannot start with a combinator")
+                    );
+                }
+                if combinator.is_some_and(|c| c != Combinator::Descendant) {
+                    return Err(location.new_custom_error("two combinators in a row"));
+                }
+                combinator = Some(next);
+            }
+            continue;
+        }
+
+        let starting = current.is_none();
+        let compound = current.get_or_insert_with(Compound::default);
+        match token {
+            Token::Ident(ref name) if starting => compound.tag = Some(name.to_ascii_lowercase()),
+            Token::Delim('*') if starting => {}
+            Token::IDHash(ref id) | Token::Hash(ref id) => {
+                compound.conditions.push(Simple::Id(id.to_string()))
+            }
+            Token::Delim('.') => {
+                let class = match parser.next_including_whitespace()? {
+                    Token::Ident(class) => class.to_string(),
+                    _ => return Err(location.new_custom_error("expected a class name after `.`")),
+                };
+                compound.conditions.push(Simple::Class(class));
+            }
+            Token::SquareBracketBlock => {
+                let condition = parser.parse_nested_block(parse_attr)?;
+                compound.conditions.push(condition);
+            }
+            Token::Colon => {
+                let condition = parse_pseudo(parser)?;
+                compound.conditions.push(condition);
+            }
+            token => return Err(location.new_unexpected_token_error(token)),
+        }
+    }
+
+    match current {
+        Some(compound) => parts.push((combinator.unwrap_or(Combinator::Descendant), compound)),
+        None if combinator.is_some_and(|c| c != Combinator::Descendant) => {
+            return Err(parser.new_custom_error("a selector cannot end with a combinator"))
+        }
+        None if parts.is_empty() => return Err(parser.new_custom_error("empty selector")),
+        None => {}
+    }
+
+    Ok(Complex { parts })
+}
+
+/// The inside of `[...]`.
+fn parse_attr<'i, 't>(parser: &mut Parser<'i, 't>) -> ParseResult<'i, Simple> {
+    let name = parser.expect_ident()?.to_ascii_lowercase();
+    if parser.is_exhausted() {
+        return Ok(Simple::Attr {
+            name,
+            op: AttrOp::Exists,
+            value: String::new(),
+        });
+    }
+
+    let location = parser.current_source_location();
+    let op = match parser.next()?.clone() {
+        Token::Delim('=') => AttrOp::Equals,
+        Token::IncludeMatch => AttrOp::Includes,
+        Token::DashMatch => AttrOp::DashMatch,
+        Token::PrefixMatch => AttrOp::Prefix,
+        Token::SuffixMatch => AttrOp::Suffix,
+        Token::SubstringMatch => AttrOp::Substring,
+        token => return Err(location.new_unexpected_token_error(token)),
+    };
+    let value = parser.expect_ident_or_string()?.to_string();
+    parser.expect_exhausted()?;
+
+    Ok(Simple::Attr { name, op, value })
+}
+
+/// What follows a `:`.
+fn parse_pseudo<'i, 't>(parser: &mut Parser<'i, 't>) -> ParseResult<'i, Simple> {
+    let location = parser.current_source_location();
+    match parser.next_including_whitespace()?.clone() {
+        Token::Ident(name) => match name.to_ascii_lowercase().as_str() {
+            "first-child" => Ok(Simple::NthChild {
+                a: 0,
+                b: 1,
+                from_end: false,
+            }),
+            "last-child" => Ok(Simple::NthChild {
+                a: 0,
+                b: 1,
+                from_end: true,
+            }),
+            "only-child" => Ok(Simple::OnlyChild),
+            _ => Err(location.new_custom_error(format!("unsupported pseudo-class `:{}`", name))),
+        },
+        Token::Function(name) => match name.to_ascii_lowercase().as_str() {
+            "nth-child" | "nth-last-child" => {
+                let from_end = name.eq_ignore_ascii_case("nth-last-child");
+                let (a, b) = parser.parse_nested_block(|parser| {
+                    let nth = cssparser::parse_nth(parser)?;
+                    parser.expect_exhausted()?;
+                    Ok(nth)
+                })?;
+                Ok(Simple::NthChild { a, b, from_end })
+            }
+            "not" => {
+                let list = parser
+                    .parse_nested_block(|parser| parser.parse_comma_separated(parse_complex))?;
+                Ok(Simple::Not(list))
+            }
+            _ => Err(location.new_custom_error(format!("unsupported pseudo-class `:{}()`", name))),
+        },
+        token => Err(location.new_unexpected_token_error(token)),
+    }
+}
+
+fn is_element(node: &Handle) -> bool {
+    traverse::local_name(node).is_some()
+}
+
+fn parent_element(node: &Handle) -> Option<Handle> {
+    traverse::parent(node).filter(is_element)
+}
+
+/// The element siblings before `node`, in document order.
+fn previous_elements(node: &Handle) -> Vec<Handle> {
+    match traverse::parent(node) {
+        Some(parent) => parent
+            .children
+            .borrow()
+            .iter()
+            .take_while(|child| !Rc::ptr_eq(child, node))
+            .filter(|child| is_element(child))
+            .cloned()
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// The position of `node` among the elements of its parent, counting from
+/// 1, and how many elements there are.
+fn element_index(node: &Handle) -> (usize, usize) {
+    let parent = match traverse::parent(node) {
+        Some(parent) => parent,
+        None => return (1, 1),
+    };
+
+    let children = parent.children.borrow();
+    let elements: Vec<&Handle> = children.iter().filter(|child| is_element(child)).collect();
+    let index = elements
+        .iter()
+        .position(|child| Rc::ptr_eq(child, node))
+        .map_or(1, |i| i + 1);
+    (index, elements.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::{collect_text, document_element, parse};
+
+    const HTML: &str = r#"<div id="content">
+        <table class="infobox vcard"><tbody>
+            <tr><th colspan="2" class="title">フランシス・ドレーク</th></tr>
+            <tr><th scope="row">生誕</th><td>1543年頃</td></tr>
+            <tr><th scope="row">死没</th><td lang="en-GB">1596年1月28日</td></tr>
+            <tr class="noprint"><th scope="row">渾名</th><td><a href="/wiki/El_Draque">エル・ドラケ</a></td></tr>
+        </tbody></table>
+        <p>一</p><h2>二</h2><p>三</p><p>四</p>
+    </div>"#;
+
+    fn texts(selector: &str) -> Vec<String> {
+        let dom = parse(HTML);
+        let root = document_element(&dom).unwrap();
+        select(&root, selector)
+            .unwrap()
+            .iter()
+            .map(collect_text)
+            .collect()
+    }
+
+    #[test]
+    fn selectors() {
+        assert_eq!(
+            texts("table.infobox th[scope=row]"),
+            ["生誕", "死没", "渾名"]
+        );
+        assert_eq!(texts("#content > p"), ["一", "三", "四"]);
+        assert_eq!(texts("tbody > tr > th.title"), ["フランシス・ドレーク"]);
+        assert!(texts("#content > tr").is_empty());
+        assert_eq!(texts("h2 + p"), ["三"]);
+        assert_eq!(texts("h2 ~ p"), ["三", "四"]);
+        assert_eq!(texts("p:first-child, h2"), ["二"]);
+        assert_eq!(
+            texts("#content > :first-child th.title"),
+            ["フランシス・ドレーク"]
+        );
+        assert_eq!(texts("#content > p:last-child"), ["四"]);
+        assert_eq!(
+            texts("tr:nth-child(2n+1) > th"),
+            ["フランシス・ドレーク", "死没"]
+        );
+        assert_eq!(texts("tr:nth-child(odd):not(:first-child) th"), ["死没"]);
+        assert_eq!(texts("tr:nth-last-child(1) td"), ["エル・ドラケ"]);
+        assert_eq!(
+            texts("tr:not(.noprint, :first-child) > td"),
+            ["1543年頃", "1596年1月28日"]
+        );
+        assert_eq!(texts("td[lang|=en]"), ["1596年1月28日"]);
+        assert_eq!(
+            texts("[class~=vcard] a[href^='/wiki/'][href$=Draque]"),
+            ["エル・ドラケ"]
+        );
+        assert_eq!(texts("a[href*=\"Dra\"]:only-child"), ["エル・ドラケ"]);
+        assert_eq!(texts("TABLE *.title"), ["フランシス・ドレーク"]);
+
+        let specificity = |s: &str| Selector::parse(s).unwrap().alternatives[0].specificity();
+        assert_eq!(specificity("table.infobox > tr th[scope]"), (0, 2, 3));
+        assert_eq!(specificity("#content :not(.a, #b) *"), (2, 0, 0));
+
+        for bad in ["", "> p", "p >", "p > > a", "p:hover", "a[href=", ".", "p,"].iter() {
+            assert!(Selector::parse(bad).is_err(), "{:?} should not parse", bad);
+        }
+        assert_eq!(
+            Selector::parse("p > > a").unwrap_err().to_string(),
+            "column 5: two combinators in a row"
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! Reading articles from disk and from the network.
+
+use crate::cache::CachedResponse;
+use crate::charset;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Downloads `url` and returns the response, with its headers.
+///
+/// See `cache::fetch` for a version that keeps responses on disk.
+#[cfg(feature = "fetch")]
+pub async fn fetch(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<CachedResponse, Box<dyn std::error::Error>> {
+    let resp = client.get(url).send().await?.error_for_status()?;
+    let headers = resp
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| {
+            value
+                .to_str()
+                .ok()
+                .map(|value| (name.as_str().to_string(), value.to_string()))
+        })
+        .collect();
+
+    Ok(CachedResponse {
+        url: url.to_string(),
+        status: resp.status().as_u16(),
+        headers,
+        body: resp.bytes().await?.to_vec(),
+    })
+}
+
+/// The file next to a saved page that names the encoding its HTTP headers
+/// declared, such as `francis_wiki.html.charset`.
+fn charset_path(path: &Path) -> PathBuf {
+    let mut charset_path = path.as_os_str().to_owned();
+    charset_path.push(".charset");
+    charset_path.into()
+}
+
+/// Writes the body of `response` to `path`.
+///
+/// If its `Content-Type` named an encoding, which the body itself may not,
+/// the encoding is written next to it for `load`.
+pub fn save<P: AsRef<Path>>(path: P, response: &CachedResponse) -> io::Result<()> {
+    let path = path.as_ref();
+    fs::write(path, &response.body)?;
+
+    match response
+        .header("content-type")
+        .and_then(charset::charset_from_content_type)
+    {
+        Some(encoding) => fs::write(charset_path(path), encoding.name()),
+        None => match fs::remove_file(charset_path(path)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Reads an HTML file in whatever encoding `save` recorded for it or,
+/// failing that, the file declares.
+///
+/// Bytes that are invalid in that encoding give an `InvalidData` error.
+/// White space is left alone; `dom::normalize_whitespace` deals with it.
+pub fn load(path: &str) -> io::Result<String> {
+    let bytes = fs::read(path)?;
+    let content_type = fs::read_to_string(charset_path(path.as_ref()))
+        .ok()
+        .map(|charset| format!("text/html; charset={}", charset.trim()));
+
+    charset::decode(&bytes, content_type.as_deref())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::SHIFT_JIS;
+
+    #[test]
+    fn save_and_load() {
+        let text = "<p>フランシス・ドレーク</p>";
+        let path =
+            std::env::temp_dir().join(format!("francis_wb_source_{}.html", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut response = CachedResponse {
+            url: "https://ja.wikipedia.org/wiki/A".to_string(),
+            status: 200,
+            headers: vec![(
+                "content-type".to_string(),
+                "text/html; charset=Shift_JIS".to_string(),
+            )],
+            body: SHIFT_JIS.encode(text).0.into_owned(),
+        };
+
+        // The encoding is only in the header, and survives the round trip.
+        save(path, &response).unwrap();
+        assert_eq!(load(path).unwrap(), text);
+
+        response.headers.clear();
+        response.body = text.as_bytes().to_vec();
+        save(path, &response).unwrap();
+        assert_eq!(load(path).unwrap(), text);
+        assert!(fs::metadata(charset_path(path.as_ref())).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+}
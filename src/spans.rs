@@ -0,0 +1,111 @@
+//! Annotations over byte ranges of text nodes.
+//!
+//! Cleanup passes flatten inline elements into plain text; a [`SpanTable`]
+//! remembers what those elements said (a link, a style) as byte ranges of
+//! the text nodes they were flattened into, and keeps the ranges right as
+//! the text is merged and its white space processed.
+
+use html5ever::rcdom::{Handle, Node, NodeData};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// A `value` over `range`, a byte range of one text node.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Span<T> {
+    pub range: Range<usize>,
+    #[serde(flatten)]
+    pub value: T,
+}
+
+/// The spans of the text nodes of one document.
+pub struct SpanTable<T> {
+    // Keyed by node address; the handle keeps the node (and the address) alive.
+    spans: HashMap<*const Node, (Handle, Vec<Span<T>>)>,
+}
+
+impl<T> Default for SpanTable<T> {
+    fn default() -> Self {
+        SpanTable {
+            spans: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Clone> SpanTable<T> {
+    /// The spans over the text node `text`, in the order they were added.
+    pub fn spans(&self, text: &Handle) -> &[Span<T>] {
+        self.spans
+            .get(&(&**text as *const Node))
+            .map_or(&[], |(_, spans)| spans)
+    }
+
+    /// Every text node under `root` that has spans, with its spans, in document order.
+    pub fn in_document(&self, root: &Handle) -> Vec<(Handle, &[Span<T>])> {
+        let mut found = vec![];
+        self.collect(root, &mut found);
+        found
+    }
+
+    fn collect<'a>(&'a self, node: &Handle, found: &mut Vec<(Handle, &'a [Span<T>])>) {
+        let spans = self.spans(node);
+        if !spans.is_empty() {
+            found.push((node.clone(), spans));
+        }
+        for child in node.children.borrow().iter() {
+            self.collect(child, found);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    fn entry(&mut self, text: &Handle) -> &mut Vec<Span<T>> {
+        &mut self
+            .spans
+            .entry(&**text as *const Node)
+            .or_insert_with(|| (text.clone(), vec![]))
+            .1
+    }
+
+    /// Puts `value` over all the text under `node`.
+    pub fn annotate(&mut self, node: &Handle, value: &T) {
+        if let NodeData::Text { ref contents } = node.data {
+            let len = contents.borrow().len();
+            self.entry(node).push(Span {
+                range: 0..len,
+                value: value.clone(),
+            });
+        }
+
+        for child in node.children.borrow().iter() {
+            self.annotate(child, value);
+        }
+    }
+
+    /// Moves the spans of `from` to `into`, for when the text of `from` is
+    /// appended to `into` at byte `offset`.
+    pub fn append(&mut self, into: &Handle, from: &Handle, offset: usize) {
+        let moved = match self.spans.remove(&(&**from as *const Node)) {
+            Some((_, spans)) => spans,
+            None => return,
+        };
+
+        self.entry(into).extend(moved.into_iter().map(|span| Span {
+            range: span.range.start + offset..span.range.end + offset,
+            value: span.value,
+        }));
+    }
+
+    /// Moves the spans of `text` after its text was rewritten; `map` is the
+    /// offset map of `whitespace::process_mapped`.
+    pub fn remap(&mut self, text: &Handle, map: &[usize]) {
+        if let Some((_, spans)) = self.spans.get_mut(&(&**text as *const Node)) {
+            for span in spans.iter_mut() {
+                span.range = map[span.range.start]..map[span.range.end];
+            }
+            spans.retain(|span| !span.range.is_empty());
+        }
+    }
+}
@@ -0,0 +1,669 @@
+//! CSS lengths and declarations, and the box properties read from them.
+
+use crate::cascade::ComputedStyle;
+use crate::layout::{BoxEdges, Edges};
+use cssparser::{Delimiter, Parser, ParserInput, Token};
+
+/// A CSS length or sizing keyword, as written.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Length {
+    /// Absolute units (`pt`, `in`, `cm`...) are converted to pixels when parsed.
+    Px(f32),
+    Em(f32),
+    Rem(f32),
+    Percent(f32),
+    Auto,
+    /// `none`, for `max-width` and `max-height`.
+    None,
+    MinContent,
+    MaxContent,
+    FitContent,
+}
+
+/// What relative lengths are relative to, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LengthContext {
+    pub font_size: f32,
+    pub root_font_size: f32,
+    /// The size of the containing block, if known, for percentages.
+    pub containing_width: Option<f32>,
+    pub containing_height: Option<f32>,
+}
+
+impl Default for LengthContext {
+    fn default() -> Self {
+        LengthContext {
+            font_size: 16.0,
+            root_font_size: 16.0,
+            containing_width: None,
+            containing_height: None,
+        }
+    }
+}
+
+impl Length {
+    /// Parses one value such as `22em`, `90%`, `1.5rem`, `0` or `auto`.
+    pub fn parse(value: &str) -> Option<Length> {
+        let mut input = ParserInput::new(value);
+        let mut parser = Parser::new(&mut input);
+
+        let length = match parser.next().ok()?.clone() {
+            Token::Dimension {
+                value, ref unit, ..
+            } => match unit.to_ascii_lowercase().as_str() {
+                "px" => Length::Px(value),
+                "em" => Length::Em(value),
+                "rem" => Length::Rem(value),
+                "pt" => Length::Px(value * 96.0 / 72.0),
+                "pc" => Length::Px(value * 16.0),
+                "in" => Length::Px(value * 96.0),
+                "cm" => Length::Px(value * 96.0 / 2.54),
+                "mm" => Length::Px(value * 96.0 / 25.4),
+                _ => return None,
+            },
+            Token::Percentage { unit_value, .. } => Length::Percent(unit_value * 100.0),
+            Token::Number { value: 0.0, .. } => Length::Px(0.0),
+            Token::Ident(ref name) => match name.to_ascii_lowercase().as_str() {
+                "auto" => Length::Auto,
+                "none" => Length::None,
+                "min-content" => Length::MinContent,
+                "max-content" => Length::MaxContent,
+                "fit-content" => Length::FitContent,
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        if parser.is_exhausted() {
+            Some(length)
+        } else {
+            None
+        }
+    }
+
+    /// The length in pixels along an axis whose containing block is
+    /// `containing` pixels long; `None` for keywords and for percentages of
+    /// an unknown size.
+    pub fn resolve(&self, ctx: &LengthContext, containing: Option<f32>) -> Option<f32> {
+        match *self {
+            Length::Px(px) => Some(px),
+            Length::Em(em) => Some(em * ctx.font_size),
+            Length::Rem(rem) => Some(rem * ctx.root_font_size),
+            Length::Percent(percent) => containing.map(|size| size * percent / 100.0),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a length rather than a keyword.
+    pub fn is_length(&self) -> bool {
+        matches!(
+            self,
+            Length::Px(_) | Length::Em(_) | Length::Rem(_) | Length::Percent(_)
+        )
+    }
+
+    fn is_negative(&self) -> bool {
+        match *self {
+            Length::Px(v) | Length::Em(v) | Length::Rem(v) | Length::Percent(v) => v < 0.0,
+            _ => false,
+        }
+    }
+}
+
+/// One `name: value` pair of a declaration list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Declaration {
+    /// Lowercased.
+    pub name: String,
+    /// The value as written, without `!important`.
+    pub value: String,
+    pub important: bool,
+}
+
+/// Splits a declaration list such as a `style` attribute into declarations,
+/// in order. Malformed declarations are skipped.
+pub fn parse_declarations(css: &str) -> Vec<Declaration> {
+    let mut input = ParserInput::new(css);
+    let mut parser = Parser::new(&mut input);
+    let mut declarations = vec![];
+
+    while !parser.is_exhausted() {
+        let declaration = parser.parse_until_after(Delimiter::Semicolon, |parser| {
+            let name = parser.expect_ident()?.to_ascii_lowercase();
+            parser.expect_colon()?;
+
+            let start = parser.position();
+            let mut end = start;
+            let mut important = false;
+            while let Ok(token) = parser.next() {
+                if *token == Token::Delim('!') {
+                    parser.expect_ident_matching("important")?;
+                    important = true;
+                    parser.expect_exhausted()?;
+                    break;
+                }
+                end = parser.position();
+            }
+
+            Ok::<_, cssparser::ParseError<()>>(Declaration {
+                name,
+                value: parser.slice(start..end).trim().to_string(),
+                important,
+            })
+        });
+        if let Ok(declaration) = declaration {
+            declarations.push(declaration);
+        }
+    }
+
+    declarations
+}
+
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+const BORDER_STYLES: &[&str] = &[
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+
+/// A `border-width` value: a length or `thin`, `medium` or `thick`.
+fn border_width(value: &str) -> Option<Length> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "thin" => Some(Length::Px(1.0)),
+        "medium" => Some(Length::Px(3.0)),
+        "thick" => Some(Length::Px(5.0)),
+        _ => Length::parse(value).filter(|length| {
+            matches!(length, Length::Px(_) | Length::Em(_) | Length::Rem(_))
+                && !length.is_negative()
+        }),
+    }
+}
+
+/// Splits the 1 to 4 values of a box shorthand such as `padding: 0.2em 0.4em`
+/// into top, right, bottom and left.
+fn four_sides(value: &str) -> Option<[&str; 4]> {
+    let values: Vec<&str> = value.split_whitespace().collect();
+    match values[..] {
+        [all] => Some([all, all, all, all]),
+        [vertical, horizontal] => Some([vertical, horizontal, vertical, horizontal]),
+        [top, horizontal, bottom] => Some([top, horizontal, bottom, horizontal]),
+        [top, right, bottom, left] => Some([top, right, bottom, left]),
+        _ => None,
+    }
+}
+
+/// The width and style of a `border` shorthand; what is left out is
+/// `medium` and `none`. Colours are skipped.
+fn border_parts(value: &str) -> Option<(String, String)> {
+    let mut width = None;
+    let mut style = None;
+    for part in value.split_whitespace() {
+        let lower = part.to_ascii_lowercase();
+        if BORDER_STYLES.contains(&lower.as_str()) && style.is_none() {
+            style = Some(lower);
+        } else if border_width(part).is_some() && width.is_none() {
+            width = Some(part.to_string());
+        } else if border_width(part).is_some() || BORDER_STYLES.contains(&lower.as_str()) {
+            return None;
+        }
+    }
+    Some((
+        width.unwrap_or_else(|| "medium".to_string()),
+        style.unwrap_or_else(|| "none".to_string()),
+    ))
+}
+
+/// The longhand declarations a box shorthand (`margin`, `padding`,
+/// `border`, `border-top`..., `border-width`, `border-style`) stands for.
+/// Other declarations are returned as they are; an invalid shorthand
+/// gives nothing.
+pub fn expand_shorthand(declaration: &Declaration) -> Vec<Declaration> {
+    let longhand = |name: String, value: &str| Declaration {
+        name,
+        value: value.to_string(),
+        important: declaration.important,
+    };
+    let name = declaration.name.as_str();
+    let value = declaration.value.as_str();
+    let css_wide = matches!(
+        value.to_ascii_lowercase().as_str(),
+        "inherit" | "initial" | "unset"
+    );
+
+    let longhands = |names: &dyn Fn(&str) -> Vec<String>| -> Vec<Declaration> {
+        SIDES
+            .iter()
+            .flat_map(|side| names(side))
+            .map(|name| longhand(name, value))
+            .collect()
+    };
+
+    match name {
+        "margin" | "padding" | "border-width" | "border-style" => {
+            let (prefix, suffix) = match name {
+                "border-width" => ("border", "-width"),
+                "border-style" => ("border", "-style"),
+                _ => (name, ""),
+            };
+            if css_wide {
+                return longhands(&|side| vec![format!("{}-{}{}", prefix, side, suffix)]);
+            }
+            match four_sides(value) {
+                Some(values) => SIDES
+                    .iter()
+                    .zip(values.iter())
+                    .map(|(side, value)| longhand(format!("{}-{}{}", prefix, side, suffix), value))
+                    .collect(),
+                None => vec![],
+            }
+        }
+        "border" | "border-top" | "border-right" | "border-bottom" | "border-left" => {
+            let sides: Vec<&str> = match name.strip_prefix("border-") {
+                Some(side) => vec![side],
+                None => SIDES.to_vec(),
+            };
+            let (width, style) = if css_wide {
+                (value.to_string(), value.to_string())
+            } else {
+                match border_parts(value) {
+                    Some(parts) => parts,
+                    None => return vec![],
+                }
+            };
+            sides
+                .iter()
+                .flat_map(|side| {
+                    vec![
+                        longhand(format!("border-{}-width", side), &width),
+                        longhand(format!("border-{}-style", side), &style),
+                    ]
+                })
+                .collect()
+        }
+        _ => vec![declaration.clone()],
+    }
+}
+
+/// Which box `width` and `height` size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BoxSizing {
+    /// The content, inside the padding and border.
+    ContentBox,
+    /// The border box, padding and border included.
+    BorderBox,
+}
+
+/// The box properties of a block: its size constraints, margin, border and padding.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockProps {
+    pub width: Length,
+    pub height: Length,
+
+    pub min_width: Length,
+    pub min_height: Length,
+
+    pub max_width: Length,
+    pub max_height: Length,
+
+    pub margin: Edges<Length>,
+    pub padding: Edges<Length>,
+    /// The `border-*-width`s; a side has no border unless its style is set too.
+    pub border_width: Edges<Length>,
+    /// Whether each side has a `border-*-style` other than `none` or `hidden`.
+    pub border_style: Edges<bool>,
+
+    pub box_sizing: BoxSizing,
+}
+
+/// `BlockProps` in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResolvedProps {
+    /// `None` for `auto` and other sizes that depend on the content.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+
+    pub min_width: u32,
+    pub min_height: u32,
+
+    pub max_width: u32,
+    pub max_height: u32,
+
+    pub edges: BoxEdges,
+    pub box_sizing: BoxSizing,
+}
+
+impl ResolvedProps {
+    /// The used width of the border box, given how wide the content is on
+    /// its own.
+    pub fn border_box_width(&self, content: u32) -> u32 {
+        let edges = self.edges.horizontal();
+        let size = self
+            .width
+            .map_or(content + edges, |width| self.border_box_of(width, edges));
+        size.min(self.border_box_of(self.max_width, edges))
+            .max(self.border_box_of(self.min_width, edges))
+    }
+
+    /// The used height of the border box, given how tall the content is on
+    /// its own.
+    pub fn border_box_height(&self, content: u32) -> u32 {
+        let edges = self.edges.vertical();
+        let size = self
+            .height
+            .map_or(content + edges, |height| self.border_box_of(height, edges));
+        size.min(self.border_box_of(self.max_height, edges))
+            .max(self.border_box_of(self.min_height, edges))
+    }
+
+    /// A `width` or `height` value as the size of the border box.
+    fn border_box_of(&self, size: u32, edges: u32) -> u32 {
+        match self.box_sizing {
+            BoxSizing::ContentBox => size.saturating_add(edges),
+            BoxSizing::BorderBox => size.max(edges),
+        }
+    }
+}
+
+impl Default for BlockProps {
+    fn default() -> Self {
+        BlockProps::new()
+    }
+}
+
+impl BlockProps {
+    pub fn new() -> Self {
+        BlockProps {
+            width: Length::Auto,
+            height: Length::Auto,
+            min_width: Length::Auto,
+            min_height: Length::Auto,
+            max_width: Length::None,
+            max_height: Length::None,
+            margin: Edges::uniform(Length::Px(0.0)),
+            padding: Edges::uniform(Length::Px(0.0)),
+            border_width: Edges::uniform(Length::Px(3.0)),
+            border_style: Edges::uniform(false),
+            box_sizing: BoxSizing::ContentBox,
+        }
+    }
+
+    /// Reads the box properties out of a declaration list such as a `style` attribute.
+    ///
+    /// Shorthands are expanded first. A later declaration of a property wins
+    /// over an earlier one, unless only the earlier one is `!important`.
+    /// Values that do not parse, and negative sizes, are ignored.
+    pub fn new_from(css: &str) -> Self {
+        let mut block_props = BlockProps::new();
+        let mut important: Vec<String> = vec![];
+
+        for declaration in parse_declarations(css).iter().flat_map(expand_shorthand) {
+            if important.contains(&declaration.name) && !declaration.important {
+                continue;
+            }
+            if block_props.set(&declaration.name, &declaration.value) && declaration.important {
+                important.push(declaration.name);
+            }
+        }
+
+        block_props
+    }
+
+    /// Reads the box properties of an element's computed style.
+    pub fn from_computed(style: &ComputedStyle) -> Self {
+        let mut block_props = BlockProps::new();
+        for (name, value) in style.properties() {
+            block_props.set(name, value);
+        }
+        block_props
+    }
+
+    /// Sets the longhand property `name`; whether it is one of these
+    /// properties and `value` is valid for it.
+    fn set(&mut self, name: &str, value: &str) -> bool {
+        let size = match name {
+            "width" => Some(&mut self.width),
+            "height" => Some(&mut self.height),
+            "min-width" => Some(&mut self.min_width),
+            "min-height" => Some(&mut self.min_height),
+            "max-width" => Some(&mut self.max_width),
+            "max-height" => Some(&mut self.max_height),
+            _ => None,
+        };
+        if let Some(field) = size {
+            return match Length::parse(value) {
+                Some(length) if !length.is_negative() => {
+                    *field = length;
+                    true
+                }
+                _ => false,
+            };
+        }
+
+        if name == "box-sizing" {
+            self.box_sizing = match value.trim().to_ascii_lowercase().as_str() {
+                "content-box" => BoxSizing::ContentBox,
+                "border-box" => BoxSizing::BorderBox,
+                _ => return false,
+            };
+            return true;
+        }
+
+        if let Some(side) = name.strip_prefix("margin-") {
+            let length =
+                Length::parse(value).filter(|length| length.is_length() || *length == Length::Auto);
+            if let (Some(field), Some(length)) = (self.margin.side_mut(side), length) {
+                *field = length;
+                return true;
+            }
+        } else if let Some(side) = name.strip_prefix("padding-") {
+            let length =
+                Length::parse(value).filter(|length| length.is_length() && !length.is_negative());
+            if let (Some(field), Some(length)) = (self.padding.side_mut(side), length) {
+                *field = length;
+                return true;
+            }
+        } else if let Some(side) = name
+            .strip_prefix("border-")
+            .and_then(|n| n.strip_suffix("-width"))
+        {
+            if let (Some(field), Some(length)) =
+                (self.border_width.side_mut(side), border_width(value))
+            {
+                *field = length;
+                return true;
+            }
+        } else if let Some(side) = name
+            .strip_prefix("border-")
+            .and_then(|n| n.strip_suffix("-style"))
+        {
+            let style = value.trim().to_ascii_lowercase();
+            if let (Some(field), true) = (
+                self.border_style.side_mut(side),
+                BORDER_STYLES.contains(&style.as_str()),
+            ) {
+                *field = !matches!(style.as_str(), "none" | "hidden");
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The constraints and edges in pixels.
+    pub fn resolve(&self, ctx: &LengthContext) -> ResolvedProps {
+        let px = |length: Length, containing: Option<f32>| {
+            length.resolve(ctx, containing).map(|px| px.round() as u32)
+        };
+        // Percentages of margins and padding are of the containing block's width.
+        let side = |length: Length| length.resolve(ctx, ctx.containing_width).unwrap_or(0.0);
+        let border = |width: Length, style: bool| {
+            if style {
+                px(width, None).unwrap_or(0)
+            } else {
+                0
+            }
+        };
+
+        let edges = BoxEdges {
+            margin: Edges {
+                top: side(self.margin.top).round() as i32,
+                right: side(self.margin.right).round() as i32,
+                bottom: side(self.margin.bottom).round() as i32,
+                left: side(self.margin.left).round() as i32,
+            },
+            border: Edges {
+                top: border(self.border_width.top, self.border_style.top),
+                right: border(self.border_width.right, self.border_style.right),
+                bottom: border(self.border_width.bottom, self.border_style.bottom),
+                left: border(self.border_width.left, self.border_style.left),
+            },
+            padding: Edges {
+                top: side(self.padding.top).round() as u32,
+                right: side(self.padding.right).round() as u32,
+                bottom: side(self.padding.bottom).round() as u32,
+                left: side(self.padding.left).round() as u32,
+            },
+        };
+
+        ResolvedProps {
+            width: px(self.width, ctx.containing_width),
+            height: px(self.height, ctx.containing_height),
+            min_width: px(self.min_width, ctx.containing_width).unwrap_or(u32::MIN),
+            min_height: px(self.min_height, ctx.containing_height).unwrap_or(u32::MIN),
+            max_width: px(self.max_width, ctx.containing_width).unwrap_or(u32::MAX),
+            max_height: px(self.max_height, ctx.containing_height).unwrap_or(u32::MAX),
+            edges,
+            box_sizing: self.box_sizing,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_css() {
+        let css = "max-width: 400px; width: 300px; height: 200px;";
+        let block_props = BlockProps::new_from(css);
+
+        assert_eq!(block_props.width, Length::Px(300.0));
+        assert_eq!(block_props.height, Length::Px(200.0));
+        assert_eq!(block_props.max_width, Length::Px(400.0));
+
+        let resolved = block_props.resolve(&LengthContext::default());
+        assert_eq!(resolved.width, Some(300));
+        assert_eq!(resolved.max_width, 400);
+        assert_eq!(resolved.max_height, u32::MAX);
+    }
+
+    #[test]
+    fn lengths() {
+        assert_eq!(Length::parse("22em"), Some(Length::Em(22.0)));
+        assert_eq!(Length::parse(" 1.5REM "), Some(Length::Rem(1.5)));
+        assert_eq!(Length::parse("90%"), Some(Length::Percent(90.0)));
+        assert_eq!(Length::parse("12pt"), Some(Length::Px(16.0)));
+        assert_eq!(Length::parse("0"), Some(Length::Px(0.0)));
+        assert_eq!(Length::parse("auto"), Some(Length::Auto));
+        assert_eq!(Length::parse("fit-content"), Some(Length::FitContent));
+        for bad in ["12", "3furlongs", "10px 20px", "calc(1px + 1em)", ""].iter() {
+            assert_eq!(Length::parse(bad), None, "{:?}", bad);
+        }
+
+        let css = "width: 22em !important; width: 10px; height: 50%; height: 1.5em; \
+                   min-width: -4px; bogus; max-height: 200px; max-width: 90% ! IMPORTANT";
+        let block_props = BlockProps::new_from(css);
+        assert_eq!(block_props.width, Length::Em(22.0));
+        assert_eq!(block_props.height, Length::Em(1.5));
+        assert_eq!(block_props.min_width, Length::Auto);
+        assert_eq!(block_props.max_height, Length::Px(200.0));
+        assert_eq!(block_props.max_width, Length::Percent(90.0));
+
+        let ctx = LengthContext {
+            font_size: 14.0,
+            containing_width: Some(500.0),
+            ..LengthContext::default()
+        };
+        let resolved = block_props.resolve(&ctx);
+        assert_eq!(resolved.width, Some(308));
+        assert_eq!(resolved.height, Some(21));
+        assert_eq!(resolved.min_width, 0);
+        assert_eq!(resolved.max_width, 450);
+        assert_eq!(resolved.max_height, 200);
+
+        // A percentage of an unknown size is like `auto`.
+        let unknown = BlockProps::new_from("width: 50%").resolve(&LengthContext::default());
+        assert_eq!(unknown.width, None);
+    }
+
+    #[test]
+    fn box_model() {
+        let expand = |css: &str| -> Vec<String> {
+            parse_declarations(css)
+                .iter()
+                .flat_map(expand_shorthand)
+                .map(|d| format!("{}={}", d.name, d.value))
+                .collect()
+        };
+        assert_eq!(
+            expand("padding: 0.2em 0.4em"),
+            [
+                "padding-top=0.2em",
+                "padding-right=0.4em",
+                "padding-bottom=0.2em",
+                "padding-left=0.4em"
+            ]
+        );
+        assert_eq!(
+            expand("margin: 1px 2px 3px"),
+            [
+                "margin-top=1px",
+                "margin-right=2px",
+                "margin-bottom=3px",
+                "margin-left=2px"
+            ]
+        );
+        assert_eq!(
+            expand("border-top: thin dotted red"),
+            ["border-top-width=thin", "border-top-style=dotted"]
+        );
+        assert_eq!(expand("border-width: inherit").len(), 4);
+        assert!(expand("margin: 1px 2px 3px 4px 5px").is_empty());
+        assert!(expand("border: solid dashed").is_empty());
+
+        let css = "padding: 0.2em 0.4em; padding-left: 10%; border: 1px solid #a2a9b1; \
+                   border-right-style: none; border-bottom: thick double; margin: 0 auto -4px; \
+                   width: 200px; box-sizing: border-box";
+        let ctx = LengthContext {
+            font_size: 10.0,
+            containing_width: Some(300.0),
+            ..LengthContext::default()
+        };
+        let resolved = BlockProps::new_from(css).resolve(&ctx);
+        let edges = |top, right, bottom, left| Edges {
+            top,
+            right,
+            bottom,
+            left,
+        };
+        assert_eq!(resolved.edges.padding, edges(2, 4, 2, 30));
+        assert_eq!(resolved.edges.border, edges(1, 0, 5, 1));
+        assert_eq!(
+            (
+                resolved.edges.margin.vertical(),
+                resolved.edges.margin.bottom
+            ),
+            (-4, -4)
+        );
+        assert_eq!(resolved.border_box_width(500), 200);
+        assert_eq!(resolved.border_box_height(20), 30);
+
+        let content_box = BlockProps::new_from("width: 200px; padding: 10px; border: 3px solid")
+            .resolve(&LengthContext::default());
+        assert_eq!(content_box.border_box_width(0), 226);
+        let squeezed = BlockProps::new_from("box-sizing: border-box; width: 4px; padding: 5px")
+            .resolve(&LengthContext::default());
+        assert_eq!(squeezed.border_box_width(0), 10);
+        // A border needs a style to be drawn.
+        let unstyled = BlockProps::new_from("border-width: 4px").resolve(&LengthContext::default());
+        assert_eq!(unstyled.edges.border, Edges::uniform(0));
+    }
+}
@@ -0,0 +1,700 @@
+//! Table grid construction and layout.
+
+use crate::cascade::ComputedStyles;
+use crate::dom::{find_elements, get_attr, get_elem_name};
+use crate::layout::{BoxEdges, DeviceContext, Point, Region, Size, TestDC};
+use crate::style::{BlockProps, LengthContext};
+use crate::text::{collect_runs, StyleTable, TextBlock, TextStyle};
+use html5ever::rcdom::Handle;
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    fmt::{Display, Formatter},
+};
+
+/// One `th`/`td` cell and the grid rows and columns it covers.
+pub struct TableCell {
+    pub text_block: TextBlock,
+    pub row_range: Vec<u32>,
+    pub col_range: Vec<u32>,
+    /// Whether the cell is a `th`.
+    pub header: bool,
+    /// The `scope` attribute of a `th`: `row`, `col`, `rowgroup` or `colgroup`.
+    pub scope: Option<String>,
+    /// The cell's border and padding around its text; `text_block`'s
+    /// position and size are of the border box.
+    pub edges: BoxEdges,
+}
+
+impl TableCell {
+    pub fn new_from(text_block: TextBlock) -> Self {
+        TableCell {
+            text_block,
+            row_range: vec![],
+            col_range: vec![],
+            header: false,
+            scope: None,
+            edges: BoxEdges::default(),
+        }
+    }
+
+    /// Where the cell's text goes, inside its border and padding.
+    pub fn content_region(&self) -> Region {
+        self.edges.content_region(Region {
+            pos: self.text_block.pos.get(),
+            size: self.text_block.size.get(),
+        })
+    }
+
+    /// Whether the cell heads the column(s) below it rather than its row.
+    pub fn is_column_header(&self) -> bool {
+        self.header && !matches!(self.scope.as_deref(), Some("row") | Some("rowgroup"))
+    }
+}
+
+/// What `Table::grid` puts in the slots a spanning cell covers besides its first one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpanFill {
+    /// The cell's text, in every slot it covers.
+    Repeat,
+    /// An empty string.
+    Empty,
+}
+
+/// A table as rows of cell text, with the spans resolved.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TableGrid {
+    /// One name per column, from the header rows; `None` if there are none.
+    pub header: Option<Vec<String>>,
+    /// The rows below the header, each `cols` long.
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Display for TableCell {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Cell = row_range: {:?}, col_range: {:?}\n {:?}",
+            self.row_range, self.col_range, self.text_block
+        )
+    }
+}
+
+/// The grid and geometry of an HTML `<table>`.
+pub struct Table {
+    pub block_props: Cell<BlockProps>,
+    /// The font sizes the table's `em` and `rem` lengths are relative to.
+    pub length_context: LengthContext,
+    /// The table's margin, border and padding; `size` is of the border box.
+    pub edges: BoxEdges,
+    pub rows: u32,
+    pub cols: u32,
+    pub min_width_cols: Vec<u32>,
+    pub max_width_cols: Vec<u32>,
+    pub size: Size,
+    pub cells: Vec<TableCell>,
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        Table::new()
+    }
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Table {
+            block_props: Cell::new(BlockProps::new()),
+            length_context: LengthContext::default(),
+            edges: BoxEdges::default(),
+            rows: 0,
+            cols: 0,
+            min_width_cols: vec![],
+            max_width_cols: vec![],
+            size: Size::new(),
+            cells: vec![],
+        }
+    }
+
+    /// Resolves the spans into a grid of text.
+    ///
+    /// The header is the leading rows made only of column headers (`th`
+    /// without `scope="row"`); stacked header rows are joined per column.
+    pub fn grid(&self, fill: SpanFill) -> TableGrid {
+        let rows = self.rows as usize;
+        let cols = self.cols as usize;
+
+        let mut slots: Vec<Vec<Option<&TableCell>>> = vec![vec![None; cols]; rows];
+        for cell in self.cells.iter() {
+            for &row in cell.row_range.iter() {
+                for &col in cell.col_range.iter() {
+                    slots[row as usize][col as usize] = Some(cell);
+                }
+            }
+        }
+
+        let text = |row: usize, col: usize, fill: SpanFill| match slots[row][col] {
+            Some(cell)
+                if fill == SpanFill::Repeat
+                    || (cell.row_range[0] as usize == row && cell.col_range[0] as usize == col) =>
+            {
+                cell.text_block.text.clone()
+            }
+            _ => String::new(),
+        };
+
+        let header_rows = (0..rows)
+            .take_while(|&row| {
+                let starting: Vec<&TableCell> = self
+                    .cells
+                    .iter()
+                    .filter(|cell| cell.row_range[0] as usize == row)
+                    .collect();
+                !starting.is_empty() && starting.iter().all(|cell| cell.is_column_header())
+            })
+            .count();
+
+        let header = if header_rows == 0 {
+            None
+        } else {
+            Some(
+                (0..cols)
+                    .map(|col| {
+                        let mut names: Vec<String> = vec![];
+                        for row in 0..header_rows {
+                            let name = text(row, col, SpanFill::Repeat);
+                            if !name.is_empty() && names.last() != Some(&name) {
+                                names.push(name);
+                            }
+                        }
+                        names.join(" ")
+                    })
+                    .collect(),
+            )
+        };
+
+        TableGrid {
+            header,
+            rows: (header_rows..rows)
+                .map(|row| (0..cols).map(|col| text(row, col, fill)).collect())
+                .collect(),
+        }
+    }
+
+    fn calc_cols(&self) -> u32 {
+        self.cells
+            .iter()
+            .map(|cell| cell.col_range.iter().max().unwrap_or(&0))
+            .max()
+            .map(|v| v + 1)
+            .unwrap_or(0)
+    }
+
+    fn calc_max_width_cols(&self) -> Vec<u32> {
+        (0..self.cols)
+            .map(|col| {
+                self.cells
+                    .iter()
+                    .map(|cell| {
+                        cell.col_range
+                            .iter()
+                            .find(|c| **c == col)
+                            .map(|_| {
+                                let ratio = 1f32 / cell.col_range.len() as f32;
+                                let block_width = cell.text_block.size.get().width;
+                                (block_width as f32 * ratio) as u32
+                            })
+                            .unwrap_or(0)
+                    })
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+
+    /// A cell spanning several rows needs an equal share of its height from each.
+    fn calc_max_height_rows(&self) -> Vec<u32> {
+        (0..self.rows)
+            .map(|row| {
+                self.cells
+                    .iter()
+                    .map(|cell| {
+                        cell.row_range
+                            .iter()
+                            .find(|r| **r == row)
+                            .map(|_| {
+                                let height = cell.text_block.size.get().height;
+                                (height as f32 / cell.row_range.len() as f32).ceil() as u32
+                            })
+                            .unwrap_or(0)
+                    })
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+
+    /// Places the border box of each cell in the table's content box.
+    fn calc_positions(&self, row_heights: &[u32]) {
+        let offsets = |sizes: &[u32]| -> Vec<u32> {
+            sizes
+                .iter()
+                .scan(0, |prev, size| {
+                    let ret = *prev;
+                    *prev += size;
+                    Some(ret)
+                })
+                .collect()
+        };
+        let xs = offsets(&self.max_width_cols);
+        let ys = offsets(row_heights);
+        let origin = self.edges.content_offset();
+
+        for cell in self.cells.iter() {
+            let row = *cell.row_range.iter().min().unwrap();
+            let col = *cell.col_range.iter().min().unwrap();
+            let x = origin.x + xs[col as usize] as i32;
+            let y = origin.y + ys[row as usize] as i32;
+            cell.text_block.pos.set(Point { x, y })
+        }
+    }
+
+    /// Stretches each cell's border box over the columns and rows it spans.
+    fn set_cell_sizes(&self, row_heights: &[u32]) {
+        for cell in self.cells.iter() {
+            let size = Size {
+                width: cell
+                    .col_range
+                    .iter()
+                    .map(|c| self.max_width_cols[*c as usize])
+                    .sum(),
+                height: cell
+                    .row_range
+                    .iter()
+                    .map(|r| row_heights[*r as usize])
+                    .sum(),
+            };
+            cell.text_block.size.set(size);
+        }
+    }
+
+    /// Builds the grid of `table_node` and lays its cells out.
+    pub fn new_from(table_node: &Handle) -> Table {
+        Table::new_from_styled(table_node, &StyleTable::default())
+    }
+
+    /// Like `new_from`, taking the styles of flattened text from `styles`.
+    pub fn new_from_styled(table_node: &Handle, styles: &StyleTable) -> Table {
+        Table::new_from_computed(
+            table_node,
+            styles,
+            &ComputedStyles::default(),
+            &TestDC::new(),
+        )
+    }
+
+    /// Like `new_from_styled`, taking the box properties of the table and
+    /// its cells, and the fonts of the cells, from their computed styles in
+    /// `computed` if they have them, or else from their `style` attributes.
+    /// The text of the cells is measured by `dc` rather than `TestDC`.
+    pub fn new_from_computed(
+        table_node: &Handle,
+        styles: &StyleTable,
+        computed: &ComputedStyles,
+        dc: &dyn DeviceContext,
+    ) -> Table {
+        let mut table = Table::new();
+
+        let (block_props, length_context) = box_props(table_node, computed);
+        table.block_props.set(block_props);
+        table.length_context = length_context;
+        let props = block_props.resolve(&length_context);
+        table.edges = props.edges;
+
+        let tbody_node = find_elements(table_node, "tbody");
+        if tbody_node.len() != 1 {
+            return table;
+        }
+
+        let tbody_node = tbody_node[0].clone();
+        let tr_nodes = find_elements(&tbody_node, "tr");
+
+        table.rows = tr_nodes.len() as u32;
+
+        // Slots taken by cells with a rowspan from the rows above.
+        let mut occupied: HashSet<(u32, u32)> = HashSet::new();
+
+        for (row, tr_node) in tr_nodes.iter().enumerate() {
+            let row = row as u32;
+            let mut col = 0u32;
+            for child in tr_node.children.borrow().iter() {
+                let tag_name = get_elem_name(child);
+                if tag_name != "th" && tag_name != "td" {
+                    continue;
+                }
+
+                while occupied.contains(&(row, col)) {
+                    col += 1;
+                }
+
+                let colspan = match get_attr(child, "colspan") {
+                    Some(s) => s.parse::<u32>().unwrap_or(1).max(1),
+                    _ => 1,
+                };
+                // rowspan="0" spans the rest of the table.
+                let rowspan = match get_attr(child, "rowspan") {
+                    Some(s) => match s.parse::<u32>().unwrap_or(1) {
+                        0 => table.rows - row,
+                        n => n.min(table.rows - row),
+                    },
+                    _ => 1,
+                };
+
+                let col_range = col..(col + colspan);
+                let row_range = row..(row + rowspan);
+                col += colspan;
+
+                for r in row_range.clone() {
+                    for c in col_range.clone() {
+                        occupied.insert((r, c));
+                    }
+                }
+
+                let runs = collect_runs(child, styles, computed);
+                if runs.is_empty() {
+                    continue;
+                }
+
+                let text_style = match computed.get(child) {
+                    Some(style) => TextStyle::from_computed(style),
+                    None => get_attr(child, "style")
+                        .map_or_else(TextStyle::default, |css| TextStyle::from_css(&css)),
+                };
+                let mut cell = TableCell::new_from(TextBlock::new_styled(runs, text_style, dc));
+                let (cell_props, cell_context) = box_props(child, computed);
+                let cell_props = cell_props.resolve(&cell_context);
+                let content = cell.text_block.size.get();
+                cell.text_block.size.set(Size {
+                    width: cell_props.border_box_width(content.width),
+                    height: cell_props.border_box_height(content.height),
+                });
+                cell.edges = cell_props.edges;
+                cell.row_range.extend(row_range);
+                cell.col_range.extend(col_range);
+                cell.header = tag_name == "th";
+                cell.scope = get_attr(child, "scope").map(|s| s.trim().to_ascii_lowercase());
+
+                table.cells.push(cell);
+            }
+        }
+
+        table.cols = table.calc_cols();
+        table.max_width_cols = table.calc_max_width_cols();
+
+        let row_heights = table.calc_max_height_rows();
+        table.calc_positions(&row_heights);
+        table.set_cell_sizes(&row_heights);
+
+        // Nothing is known of the containing block, so percentages are ignored.
+        table.size = Size {
+            width: props.border_box_width(table.max_width_cols.iter().sum()),
+            height: props.border_box_height(row_heights.iter().sum()),
+        };
+
+        table
+    }
+}
+
+/// The box properties of `node` and what its lengths are relative to, from
+/// its computed style in `computed` if it has one, or else from its `style`
+/// attribute.
+fn box_props(node: &Handle, computed: &ComputedStyles) -> (BlockProps, LengthContext) {
+    match computed.get(node) {
+        Some(style) => (BlockProps::from_computed(style), style.length_context()),
+        None => (
+            get_attr(node, "style").map_or_else(BlockProps::new, |css| BlockProps::new_from(&css)),
+            LengthContext::default(),
+        ),
+    }
+}
+
+impl Display for Table {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Table = rows: {}, cols: {}, min_width_cols: {:?}, max_width_cols: {:?}",
+            self.rows, self.cols, self.min_width_cols, self.max_width_cols
+        )?;
+
+        for (i, cell) in self.cells.iter().enumerate() {
+            writeln!(f, "{}: {:}\n", i, cell)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::parse;
+    use crate::layout::Edges;
+
+    #[test]
+    fn table() {
+        let html_data = r##"
+        <table>
+        <tbody>
+            <tr>
+                <td colspan="2">1543年頃 - 1596年1月28日</td>
+            </tr>
+            <tr>
+                <th>生誕</th>
+                <td>イングランド、デヴォン、タヴィストック</td>
+            </tr>
+            <tr>
+                <th>最終階級</th>
+                <td>イギリス海軍中将</td>
+            </tr>
+        </tbody>
+        </table>
+        "##;
+
+        // max-width
+        // ----------
+        // 380
+        //  40, 380
+        //  80, 160
+        // ----------
+        // 190, 380
+
+        let dom = parse(html_data);
+        let node = &dom.document.children.borrow()[0];
+
+        let table_nodes = find_elements(node, "table");
+        let table = Table::new_from(&table_nodes[0]);
+        //println!("{:}", table);
+
+        assert_eq!(table.rows, 3);
+        assert_eq!(table.cols, 2);
+
+        assert_eq!(
+            table.size,
+            Size {
+                width: 570,
+                height: 60
+            }
+        );
+
+        assert_eq!(table.cells[0].text_block.pos.get(), Point { x: 0, y: 0 });
+        assert_eq!(table.cells[1].text_block.pos.get(), Point { x: 0, y: 20 });
+        assert_eq!(table.cells[2].text_block.pos.get(), Point { x: 190, y: 20 });
+        assert_eq!(table.cells[3].text_block.pos.get(), Point { x: 0, y: 40 });
+        assert_eq!(table.cells[4].text_block.pos.get(), Point { x: 190, y: 40 });
+
+        assert_eq!(table.cells[0].text_block.size.get().width, 570);
+        assert_eq!(table.cells[1].text_block.size.get().width, 190);
+        assert_eq!(table.cells[2].text_block.size.get().width, 380);
+        assert_eq!(table.cells[3].text_block.size.get().width, 190);
+        assert_eq!(table.cells[4].text_block.size.get().width, 380);
+    }
+
+    #[test]
+    fn table_width() {
+        let html_data = r##"
+        <table style="width: 300px;">
+        <tbody>
+            <tr>
+                <td colspan="2">1543年頃 - 1596年1月28日</td>
+            </tr>
+            <tr>
+                <th>生誕</th>
+                <td>イングランド、デヴォン、タヴィストック</td>
+            </tr>
+            <tr>
+                <th>最終階級</th>
+                <td>イギリス海軍中将</td>
+            </tr>
+        </tbody>
+        </table>
+        "##;
+
+        // max-width
+        // ----------
+        // 380
+        //  40, 380
+        //  80, 160
+        // ----------
+        // 190, 380  = 570
+
+        // width = 300
+        // 100, 200
+
+        let dom = parse(html_data);
+        let node = &dom.document.children.borrow()[0];
+
+        let table_nodes = find_elements(node, "table");
+        let table = Table::new_from(&table_nodes[0]);
+
+        assert_eq!(table.size.width, 300);
+        //println!("{:}", table);
+    }
+
+    #[test]
+    fn box_model() {
+        let html_data = r##"
+        <table style="padding: 5px; border: 2px solid">
+        <tbody>
+            <tr><td style="padding: 1px 4px">ab</td><td>c</td></tr>
+            <tr><td colspan="2" style="border: 1px solid">de</td></tr>
+        </tbody>
+        </table>
+        "##;
+
+        let dom = parse(html_data);
+        let table = Table::new_from(&find_elements(&dom.document, "table")[0]);
+
+        assert_eq!(table.max_width_cols, [48, 21]);
+        assert_eq!(
+            table.size,
+            Size {
+                width: 83,
+                height: 58
+            }
+        );
+        let regions: Vec<(Point, Size)> = table
+            .cells
+            .iter()
+            .map(|cell| (cell.text_block.pos.get(), cell.text_block.size.get()))
+            .collect();
+        let region = |x, y, width, height| (Point { x, y }, Size { width, height });
+        assert_eq!(
+            regions,
+            [
+                region(7, 7, 48, 22),
+                region(55, 7, 21, 22),
+                region(7, 29, 69, 22)
+            ]
+        );
+        assert_eq!(
+            table.cells[0].content_region(),
+            Region {
+                pos: Point { x: 11, y: 8 },
+                size: Size {
+                    width: 40,
+                    height: 20
+                },
+            }
+        );
+
+        // The default style sheet pads every cell by 1px.
+        let padding = |pipeline: crate::Pipeline| {
+            pipeline.parse(html_data).tables()[0].cells[1].edges.padding
+        };
+        assert_eq!(padding(crate::Pipeline::new()), Edges::uniform(1));
+        assert_eq!(
+            padding(crate::Pipeline::new().user_agent_styles(false)),
+            Edges::uniform(0)
+        );
+    }
+
+    /// Half-width glyphs for ASCII, full-width ones for the rest.
+    struct HalfWidthDC;
+
+    impl DeviceContext for HalfWidthDC {
+        fn measure_text(&self, text: &str, style: &TextStyle) -> Size {
+            let width: f32 = text
+                .chars()
+                .map(|c| if c.is_ascii() { 0.5 } else { 1.0 })
+                .sum();
+            Size {
+                width: (width * style.size) as u32,
+                height: style.size as u32,
+            }
+        }
+    }
+
+    #[test]
+    fn device_context() {
+        let doc = crate::Pipeline::new()
+            .user_agent_styles(false)
+            .parse("<table><tr><td>1577年</td><td>ドレーク</td></tr></table>");
+
+        let table = &doc.tables_measured_by(&HalfWidthDC)[0];
+        assert_eq!(table.max_width_cols, [48, 64]);
+        assert_eq!(table.size.height, 16);
+        assert_eq!(doc.tables()[0].max_width_cols, [100, 80]);
+    }
+
+    #[test]
+    fn rowspan_height() {
+        let html_data = r##"
+        <table>
+        <tbody>
+            <tr><th rowspan="2" style="height: 60px">年</th><td>1577年</td></tr>
+            <tr><td>1585年</td></tr>
+            <tr><td colspan="2">出典</td></tr>
+        </tbody>
+        </table>
+        "##;
+
+        let dom = parse(html_data);
+        let table = Table::new_from(&find_elements(&dom.document, "table")[0]);
+
+        // The 60px header is shared by the two rows it spans.
+        assert_eq!(table.calc_max_height_rows(), [30, 30, 20]);
+        assert_eq!(table.size.height, 80);
+        assert_eq!(table.cells[0].text_block.size.get().height, 60);
+        assert_eq!(table.cells[2].text_block.pos.get(), Point { x: 20, y: 30 });
+    }
+
+    #[test]
+    fn grid() {
+        let html_data = r##"
+        <table class="wikitable">
+        <tbody>
+            <tr><th rowspan="2">年</th><th colspan="2">艦隊</th></tr>
+            <tr><th>旗艦</th><th>隻数</th></tr>
+            <tr><th scope="row">1577年</th><td>ゴールデン・ハインド</td><td rowspan="2">5</td></tr>
+            <tr><th scope="row">1585年</th><td>エリザベス・ボナヴェンチャー</td></tr>
+            <tr><td colspan="3">出典</td></tr>
+        </tbody>
+        </table>
+        "##;
+
+        let dom = parse(html_data);
+        let node = &dom.document.children.borrow()[0];
+        let table = Table::new_from(&find_elements(node, "table")[0]);
+
+        assert_eq!(table.rows, 5);
+        assert_eq!(table.cols, 3);
+        // 年 spans down into the second row, so 旗艦 starts in the second column.
+        assert_eq!(table.cells[2].col_range, vec![1]);
+        assert_eq!(table.cells[6].row_range, vec![2, 3]);
+
+        let grid = table.grid(SpanFill::Repeat);
+        assert_eq!(
+            grid.header,
+            Some(vec![
+                "年".to_string(),
+                "艦隊 旗艦".to_string(),
+                "艦隊 隻数".to_string()
+            ])
+        );
+        assert_eq!(
+            grid.rows,
+            vec![
+                vec!["1577年", "ゴールデン・ハインド", "5"],
+                vec!["1585年", "エリザベス・ボナヴェンチャー", "5"],
+                vec!["出典", "出典", "出典"],
+            ]
+        );
+
+        let grid = table.grid(SpanFill::Empty);
+        assert_eq!(
+            grid.rows[1],
+            vec!["1585年", "エリザベス・ボナヴェンチャー", ""]
+        );
+        assert_eq!(grid.rows[2], vec!["出典", "", ""]);
+    }
+}
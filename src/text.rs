@@ -0,0 +1,519 @@
+//! Styled text runs and blocks of laid-out text.
+
+use crate::cascade::{Cascade, ComputedStyle, ComputedStyles, DEFAULT_FONT_SIZE};
+use crate::dom::{element_white_space, get_attr, white_space_of};
+use crate::layout::{self, DeviceContext};
+use crate::spans::{Span, SpanTable};
+use crate::style::{parse_declarations, Length};
+use crate::traverse;
+use crate::whitespace::{self, WhiteSpace};
+use html5ever::rcdom::{Handle, NodeData};
+use serde::Serialize;
+use std::cell::Cell;
+
+/// How a run of text is drawn.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct RunStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub superscript: bool,
+    pub lang: Option<String>,
+    /// In pixels; `None` is the device's default size.
+    pub font_size: Option<u32>,
+}
+
+thread_local! {
+    // For elements the document's cascade did not reach.
+    static USER_AGENT: Cascade = Cascade::with_user_agent();
+}
+
+impl RunStyle {
+    /// What the element `node` adds to the style of the text inside it: its
+    /// `lang`, and the fonts of its style in `computed` that differ from its
+    /// parent's. Without a style there, the default styles and its `style`
+    /// attribute are cascaded over its parent's. `None` if it changes nothing.
+    pub fn from_element(node: &Handle, computed: &ComputedStyles) -> Option<Self> {
+        traverse::local_name(node)?;
+
+        let parent = traverse::parent(node)
+            .and_then(|parent| computed.get(&parent).cloned())
+            .unwrap_or_default();
+        let own = match computed.get(node) {
+            Some(own) => own.clone(),
+            None => USER_AGENT.with(|cascade| cascade.style_of(node, &parent)),
+        };
+        let (outer, inner) = (
+            TextStyle::from_computed(&parent),
+            TextStyle::from_computed(&own),
+        );
+
+        let style = RunStyle {
+            bold: inner.weight >= 600 && outer.weight < 600,
+            italic: inner.style != FontStyle::Normal && outer.style == FontStyle::Normal,
+            superscript: own
+                .get("vertical-align")
+                .is_some_and(|align| align.trim().eq_ignore_ascii_case("super")),
+            lang: get_attr(node, "lang"),
+            font_size: if inner.size != outer.size {
+                Some(inner.size.round() as u32)
+            } else {
+                None
+            },
+        };
+
+        if style == RunStyle::default() {
+            None
+        } else {
+            Some(style)
+        }
+    }
+
+    /// Adds the style of an element nested inside the one this style came from.
+    pub fn apply(&mut self, inner: &RunStyle) {
+        self.bold |= inner.bold;
+        self.italic |= inner.italic;
+        self.superscript |= inner.superscript;
+        if inner.lang.is_some() {
+            self.lang = inner.lang.clone();
+        }
+        if inner.font_size.is_some() {
+            self.font_size = inner.font_size;
+        }
+    }
+}
+
+/// Whether a font is upright or slanted.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum FontStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+/// The font properties text is measured with, in pixels.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TextStyle {
+    /// `font-family`, most preferred first; empty for the device's default.
+    pub families: Vec<String>,
+    pub size: f32,
+    /// 100 to 900; 400 is normal and 700 bold.
+    pub weight: u16,
+    pub style: FontStyle,
+    /// `None` for `normal`, which is up to the font.
+    pub line_height: Option<f32>,
+    pub letter_spacing: f32,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            families: vec![],
+            size: DEFAULT_FONT_SIZE,
+            weight: 400,
+            style: FontStyle::Normal,
+            line_height: None,
+            letter_spacing: 0.0,
+        }
+    }
+}
+
+impl TextStyle {
+    /// The font properties of an element's computed style.
+    pub fn from_computed(style: &ComputedStyle) -> Self {
+        let size = style.font_size;
+        let ctx = style.length_context();
+        let length = |value: &str| Length::parse(value).and_then(|l| l.resolve(&ctx, Some(size)));
+
+        let families = style
+            .get("font-family")
+            .map(|families| {
+                families
+                    .split(',')
+                    .map(|family| family.trim().trim_matches(&['"', '\''][..]).to_string())
+                    .filter(|family| !family.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let keyword = |name: &str| {
+            style
+                .get(name)
+                .map(|value| value.trim().to_ascii_lowercase())
+        };
+
+        let weight = match keyword("font-weight").as_deref() {
+            Some("bold") | Some("bolder") => 700,
+            Some("lighter") => 100,
+            Some(weight) => weight
+                .parse::<u16>()
+                .ok()
+                .filter(|weight| (1..=1000).contains(weight))
+                .unwrap_or(400),
+            None => 400,
+        };
+
+        let font_style = match keyword("font-style").as_deref() {
+            Some("italic") => FontStyle::Italic,
+            Some(oblique) if oblique.starts_with("oblique") => FontStyle::Oblique,
+            _ => FontStyle::Normal,
+        };
+
+        // A bare number is a multiple of the font size.
+        let line_height =
+            style
+                .get("line-height")
+                .and_then(|value| match value.trim().parse::<f32>() {
+                    Ok(factor) => Some(factor * size),
+                    Err(_) => length(value),
+                });
+
+        TextStyle {
+            families,
+            size,
+            weight,
+            style: font_style,
+            line_height,
+            letter_spacing: style.get("letter-spacing").and_then(length).unwrap_or(0.0),
+        }
+    }
+
+    /// The font properties of a `style` attribute on its own.
+    pub fn from_css(css: &str) -> Self {
+        let mut declarations = parse_declarations(css);
+        declarations.sort_by_key(|declaration| declaration.important);
+        TextStyle::from_computed(&ComputedStyle::cascade(
+            &ComputedStyle::default(),
+            &declarations,
+        ))
+    }
+
+    /// This style with what a run's inline elements change.
+    pub fn with_run(&self, run: &RunStyle) -> TextStyle {
+        let mut style = self.clone();
+        if run.bold {
+            style.weight = style.weight.max(700);
+        }
+        if run.italic {
+            style.style = FontStyle::Italic;
+        }
+        if let Some(size) = run.font_size {
+            style.size = size as f32;
+        }
+        style
+    }
+}
+
+/// A style over a byte range of one text node.
+pub type StyleSpan = Span<RunStyle>;
+
+/// The styles of flattened inline elements, for the text nodes of one document.
+pub type StyleTable = SpanTable<RunStyle>;
+
+/// A piece of text in one style.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TextRun {
+    pub text: String,
+    pub style: RunStyle,
+}
+
+impl TextRun {
+    pub fn plain(text: &str) -> Self {
+        TextRun {
+            text: text.to_string(),
+            style: RunStyle::default(),
+        }
+    }
+}
+
+/// The text under `node` as styled runs.
+///
+/// The text is the same as `dom::collect_text`. Styles come from inline
+/// elements still in the tree, as `RunStyle::from_element` finds them with
+/// `computed`, and from `styles`, which remembers the ones cleanup flattened.
+pub fn collect_runs(node: &Handle, styles: &StyleTable, computed: &ComputedStyles) -> Vec<TextRun> {
+    let mut runs = vec![];
+    collect_runs_into(
+        node,
+        white_space_of(node, computed),
+        &RunStyle::default(),
+        styles,
+        computed,
+        &mut runs,
+    );
+
+    while let Some(first) = runs.first_mut() {
+        let trimmed = first.text.trim_start_matches(whitespace::is_collapsible);
+        if trimmed.is_empty() {
+            runs.remove(0);
+        } else {
+            first.text = trimmed.to_string();
+            break;
+        }
+    }
+    while let Some(last) = runs.last_mut() {
+        let trimmed = last.text.trim_end_matches(whitespace::is_collapsible);
+        if trimmed.is_empty() {
+            runs.pop();
+        } else {
+            last.text = trimmed.to_string();
+            break;
+        }
+    }
+
+    runs
+}
+
+fn collect_runs_into(
+    node: &Handle,
+    mode: WhiteSpace,
+    inherited: &RunStyle,
+    styles: &StyleTable,
+    computed: &ComputedStyles,
+    runs: &mut Vec<TextRun>,
+) {
+    if let NodeData::Text { ref contents } = node.data {
+        let (text, map) = whitespace::process_mapped(&contents.borrow(), mode);
+
+        let mut start = 0;
+        if mode.collapses_spaces() && runs.last().is_some_and(|run| run.text.ends_with(' ')) {
+            start = text.len() - text.trim_start_matches(' ').len();
+        }
+
+        let spans: Vec<(usize, usize, &RunStyle)> = styles
+            .spans(node)
+            .iter()
+            .map(|span| (map[span.range.start], map[span.range.end], &span.value))
+            .collect();
+
+        // Cut the text wherever a span starts or ends.
+        let mut cuts = vec![start, text.len()];
+        for &(from, to, _) in spans.iter() {
+            cuts.push(from.max(start));
+            cuts.push(to.max(start));
+        }
+        cuts.sort_unstable();
+        cuts.dedup();
+
+        for cut in cuts.windows(2) {
+            let mut style = inherited.clone();
+            for &(from, to, span_style) in spans.iter() {
+                if from <= cut[0] && cut[1] <= to {
+                    style.apply(span_style);
+                }
+            }
+            push_run(runs, &text[cut[0]..cut[1]], style);
+        }
+    }
+
+    for child in node.children.borrow().iter() {
+        let mut style = inherited.clone();
+        if let Some(element_style) = RunStyle::from_element(child, computed) {
+            style.apply(&element_style);
+        }
+        collect_runs_into(
+            child,
+            element_white_space(child, mode, computed),
+            &style,
+            styles,
+            computed,
+            runs,
+        );
+    }
+}
+
+fn push_run(runs: &mut Vec<TextRun>, text: &str, style: RunStyle) {
+    if text.is_empty() {
+        return;
+    }
+
+    match runs.last_mut() {
+        Some(last) if last.style == style => last.text.push_str(text),
+        _ => runs.push(TextRun {
+            text: text.to_string(),
+            style,
+        }),
+    }
+}
+
+/// A run of text with its measured position and size.
+#[derive(Debug)]
+pub struct TextBlock {
+    pub text: String,
+    pub runs: Vec<TextRun>,
+    /// The style of the element the runs are in, before their own.
+    pub style: TextStyle,
+    pub pos: Cell<layout::Point>,
+    pub size: Cell<layout::Size>,
+    pub min_width: u32,
+    pub max_width: u32,
+}
+
+impl TextBlock {
+    pub fn new_from(text: &str) -> TextBlock {
+        TextBlock::new_from_runs(vec![TextRun::plain(text)])
+    }
+
+    /// A block of runs laid out one after another, measured by `TestDC`.
+    pub fn new_from_runs(runs: Vec<TextRun>) -> TextBlock {
+        TextBlock::new_styled(runs, TextStyle::default(), &layout::TestDC::new())
+    }
+
+    /// A block of runs in an element styled `style`, measured by `dc`.
+    pub fn new_styled(runs: Vec<TextRun>, style: TextStyle, dc: &dyn DeviceContext) -> TextBlock {
+        let size = dc.measure_runs(&runs, &style);
+        let min_width = dc.measure_text(" ", &style).width;
+        let max_width = size.width;
+
+        TextBlock {
+            text: runs.iter().map(|run| run.text.as_str()).collect(),
+            runs,
+            style,
+            pos: Cell::new(layout::Point::new()),
+            size: Cell::new(size),
+            min_width,
+            max_width,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::{collect_text, find_elements};
+    use crate::Pipeline;
+
+    fn run(text: &str, f: impl FnOnce(&mut RunStyle)) -> TextRun {
+        let mut run = TextRun::plain(text);
+        f(&mut run.style);
+        run
+    }
+
+    #[test]
+    fn runs() {
+        let html_data = "<p> フランシス・ドレーク（<span lang=\"en\"><b>Sir Francis\n Drake</b>, \
+                         <i>c.</i></span> 1543年 - 1596年）<sup>[1]</sup> \
+                         <span style=\"font-size: 12px; font-weight: 700\">卿</span> </p>";
+
+        let expected = vec![
+            TextRun::plain("フランシス・ドレーク（"),
+            run("Sir Francis Drake", |s| {
+                s.bold = true;
+                s.lang = Some("en".to_string())
+            }),
+            run(", ", |s| s.lang = Some("en".to_string())),
+            run("c.", |s| {
+                s.italic = true;
+                s.lang = Some("en".to_string())
+            }),
+            TextRun::plain(" 1543年 - 1596年）"),
+            run("[1]", |s| {
+                s.superscript = true;
+                s.font_size = Some(13)
+            }),
+            TextRun::plain(" "),
+            run("卿", |s| {
+                s.bold = true;
+                s.font_size = Some(12)
+            }),
+        ];
+
+        // The same runs whether the inline elements are still there or were flattened.
+        for pipeline in [Pipeline::new().clean(false), Pipeline::new()].iter() {
+            let doc = pipeline.parse(html_data);
+            let p = &find_elements(&doc.root().unwrap(), "p")[0];
+            let runs = collect_runs(p, doc.styles(), doc.computed_styles());
+            assert_eq!(runs, expected);
+
+            let block = TextBlock::new_from_runs(runs);
+            assert_eq!(block.text, collect_text(p));
+            // 20px glyphs, except for the 16.25px ones of the 13px `[1]` and
+            // the 15px one of the 12px `卿`.
+            assert_eq!(
+                block.size.get().width,
+                20 * (block.text.chars().count() as u32 - 4) + 64
+            );
+        }
+    }
+
+    #[test]
+    fn relative_run_styles() {
+        let html_data = "<p style='font-size: 20px'><span style='FONT-SIZE: 125%'>a</span>\
+                         <span style='font-size: 0.5em; Font-Weight: BOLD'>b</span>\
+                         <span style='font-size: larger'>c</span><small>d</small><big>e</big>\
+                         <b style='font-weight: normal'>f</b><span style='font-size: 20px'>g</span></p>";
+        let expected = vec![
+            run("a", |s| s.font_size = Some(25)),
+            run("b", |s| {
+                s.bold = true;
+                s.font_size = Some(10)
+            }),
+            run("c", |s| s.font_size = Some(24)),
+            run("d", |s| s.font_size = Some(17)),
+            run("e", |s| s.font_size = Some(24)),
+            TextRun::plain("fg"),
+        ];
+
+        let doc = Pipeline::new().parse(html_data);
+        let p = &find_elements(&doc.root().unwrap(), "p")[0];
+        assert_eq!(
+            collect_runs(p, doc.styles(), doc.computed_styles()),
+            expected
+        );
+
+        // Without computed styles, the default styles are cascaded on the spot.
+        let doc = Pipeline::new().clean(false).parse(html_data);
+        let p = &find_elements(&doc.root().unwrap(), "p")[0];
+        let runs = collect_runs(p, doc.styles(), &ComputedStyles::default());
+        assert_eq!(runs[3], run("d", |s| s.font_size = Some(13)));
+        assert_eq!(
+            runs[1],
+            run("b", |s| {
+                s.bold = true;
+                s.font_size = Some(8)
+            })
+        );
+    }
+
+    #[test]
+    fn text_style() {
+        let style = TextStyle::from_css(
+            "font-family: 'Noto Sans JP', sans-serif; font-size: 125%; font-weight: bold; \
+             font-style: oblique 10deg; line-height: 1.5; letter-spacing: 0.1em",
+        );
+        assert_eq!(
+            style,
+            TextStyle {
+                families: vec!["Noto Sans JP".to_string(), "sans-serif".to_string()],
+                size: 20.0,
+                weight: 700,
+                style: FontStyle::Oblique,
+                line_height: Some(30.0),
+                letter_spacing: 2.0,
+            }
+        );
+        let inner = style.with_run(&RunStyle {
+            italic: true,
+            font_size: Some(10),
+            ..RunStyle::default()
+        });
+        assert_eq!(
+            (inner.size, inner.style, inner.weight),
+            (10.0, FontStyle::Italic, 700)
+        );
+
+        // An infobox title measures larger than the cells below it.
+        let doc = Pipeline::new().parse(
+            "<table class=infobox><tr><th style='font-size:125%; font-weight:bold'>\
+             フランシス・ドレーク</th></tr><tr><td>渾名</td></tr></table>",
+        );
+        let table = &doc.tables()[0];
+        let title = &table.cells[0];
+        assert_eq!(
+            (title.text_block.style.size, title.text_block.style.weight),
+            (20.0, 700)
+        );
+        assert_eq!(table.max_width_cols, [252]);
+        assert_eq!(title.content_region().size.height, 25);
+        assert_eq!(table.cells[1].content_region().size.height, 20);
+    }
+}
@@ -0,0 +1,233 @@
+//! Lazy iterators over an `RcDom` tree.
+//!
+//! The walks hand out `Handle`s (a reference count bump, not a copy) and
+//! allocate nothing per node; [`Traverse`] keeps one stack as deep as the
+//! tree. Element names are compared as interned [`LocalName`]s or borrowed
+//! `&str`s instead of being copied into `String`s.
+
+use html5ever::rcdom::{Handle, Node, NodeData};
+use html5ever::LocalName;
+
+/// The local name of an element, or `None` for other nodes.
+pub fn local_name(node: &Node) -> Option<&LocalName> {
+    match node.data {
+        NodeData::Element { ref name, .. } => Some(&name.local),
+        _ => None,
+    }
+}
+
+/// Whether `node` is an element named `name`.
+pub fn is_element(node: &Node, name: &LocalName) -> bool {
+    local_name(node) == Some(name)
+}
+
+/// Entering or leaving a node in a depth-first walk.
+#[derive(Clone, Debug)]
+pub enum Edge {
+    Open(Handle),
+    Close(Handle),
+}
+
+/// A depth-first walk of a node and its descendants: each node is opened,
+/// then its children are walked, then it is closed.
+pub struct Traverse {
+    start: Option<Handle>,
+    // Each open node and the index of its next child.
+    stack: Vec<(Handle, usize)>,
+}
+
+impl Iterator for Traverse {
+    type Item = Edge;
+
+    fn next(&mut self) -> Option<Edge> {
+        if let Some(start) = self.start.take() {
+            self.stack.push((start.clone(), 0));
+            return Some(Edge::Open(start));
+        }
+
+        let (node, next) = self.stack.last_mut()?;
+        let child = node.children.borrow().get(*next).cloned();
+        match child {
+            Some(child) => {
+                *next += 1;
+                self.stack.push((child.clone(), 0));
+                Some(Edge::Open(child))
+            }
+            None => self.stack.pop().map(|(node, _)| Edge::Close(node)),
+        }
+    }
+}
+
+/// Walks `node` and its descendants, opening and closing each.
+pub fn traverse(node: &Handle) -> Traverse {
+    Traverse {
+        start: Some(node.clone()),
+        stack: vec![],
+    }
+}
+
+/// The descendants of a node in document order, not counting the node itself.
+pub struct Descendants(Traverse);
+
+impl Iterator for Descendants {
+    type Item = Handle;
+
+    fn next(&mut self) -> Option<Handle> {
+        loop {
+            match self.0.next()? {
+                Edge::Open(node) => return Some(node),
+                _ => continue,
+            }
+        }
+    }
+}
+
+/// The descendants of `node` in document order.
+pub fn descendants(node: &Handle) -> Descendants {
+    let mut walk = traverse(node);
+    walk.next();
+    Descendants(walk)
+}
+
+/// The text nodes under a node, in document order.
+pub struct TextNodes(Traverse);
+
+impl Iterator for TextNodes {
+    type Item = Handle;
+
+    fn next(&mut self) -> Option<Handle> {
+        loop {
+            match self.0.next()? {
+                Edge::Open(node) if matches!(node.data, NodeData::Text { .. }) => {
+                    return Some(node)
+                }
+                _ => continue,
+            }
+        }
+    }
+}
+
+/// The text nodes of `node` and its descendants, in document order.
+pub fn text_nodes(node: &Handle) -> TextNodes {
+    TextNodes(traverse(node))
+}
+
+/// The parent, grandparent and so on of a node, up to the document.
+pub struct Ancestors(Option<Handle>);
+
+impl Iterator for Ancestors {
+    type Item = Handle;
+
+    fn next(&mut self) -> Option<Handle> {
+        let node = self.0.take()?;
+        self.0 = parent(&node);
+        Some(node)
+    }
+}
+
+/// The ancestors of `node`, nearest first.
+pub fn ancestors(node: &Handle) -> Ancestors {
+    Ancestors(parent(node))
+}
+
+/// The siblings after a node, in document order.
+pub struct FollowingSiblings {
+    parent: Option<Handle>,
+    next: usize,
+}
+
+impl Iterator for FollowingSiblings {
+    type Item = Handle;
+
+    fn next(&mut self) -> Option<Handle> {
+        let sibling = self
+            .parent
+            .as_ref()?
+            .children
+            .borrow()
+            .get(self.next)
+            .cloned();
+        self.next += 1;
+        sibling
+    }
+}
+
+/// The siblings after `node`; none if it has no parent.
+pub fn following_siblings(node: &Handle) -> FollowingSiblings {
+    let parent = parent(node);
+    let next = parent
+        .as_ref()
+        .and_then(|parent| {
+            parent
+                .children
+                .borrow()
+                .iter()
+                .position(|child| std::ptr::eq(&**child, &**node))
+        })
+        .map_or(usize::MAX, |i| i + 1);
+
+    FollowingSiblings { parent, next }
+}
+
+/// The parent of `node`, if it has one.
+pub fn parent(node: &Handle) -> Option<Handle> {
+    let weak = node.parent.take();
+    let parent = weak.as_ref().and_then(|weak| weak.upgrade());
+    node.parent.set(weak);
+    parent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dom::{document_element, get_elem_name, parse};
+
+    fn names(nodes: impl Iterator<Item = Handle>) -> Vec<String> {
+        nodes
+            .map(|node| match node.data {
+                NodeData::Text { ref contents } => format!("'{}'", contents.borrow()),
+                _ => get_elem_name(&node),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn walks() {
+        let dom = parse("<div><p>a<b>b</b></p><ul><li>c</li><li>d</li></ul>e</div>");
+        let root = document_element(&dom).unwrap();
+        let div = descendants(&root)
+            .find(|node| is_element(node, &LocalName::from("div")))
+            .unwrap();
+
+        assert_eq!(
+            names(descendants(&div)),
+            ["p", "'a'", "b", "'b'", "ul", "li", "'c'", "li", "'d'", "'e'"]
+        );
+        assert_eq!(names(text_nodes(&div)), ["'a'", "'b'", "'c'", "'d'", "'e'"]);
+
+        let edges: Vec<String> = traverse(&div.children.borrow()[0])
+            .map(|edge| match edge {
+                Edge::Open(node) => format!("+{}", names(Some(node).into_iter())[0]),
+                Edge::Close(node) => format!("-{}", names(Some(node).into_iter())[0]),
+            })
+            .collect();
+        assert_eq!(
+            edges,
+            ["+p", "+'a'", "-'a'", "+b", "+'b'", "-'b'", "-b", "-p"]
+        );
+
+        let c = text_nodes(&div).nth(2).unwrap();
+        assert_eq!(
+            names(ancestors(&c)),
+            ["li", "ul", "div", "body", "html", ""]
+        );
+
+        let first_li = parent(&c).unwrap();
+        assert_eq!(names(following_siblings(&first_li)), ["li"]);
+        let ul = parent(&first_li).unwrap();
+        assert_eq!(names(following_siblings(&ul)), ["'e'"]);
+        assert_eq!(following_siblings(&root).count(), 0);
+        assert!(descendants(&c).next().is_none());
+        assert_eq!(local_name(&c), None);
+    }
+}
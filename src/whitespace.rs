@@ -0,0 +1,242 @@
+//! White space processing as described by the CSS `white-space` property.
+//!
+//! Only spaces, tabs and segment breaks (LF, CR, FF) are collapsible, as in
+//! CSS Text. Other Unicode spaces such as U+00A0 NO-BREAK SPACE and U+3000
+//! IDEOGRAPHIC SPACE are ordinary characters: they are never collapsed or
+//! trimmed.
+
+/// The values of the CSS `white-space` property.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WhiteSpace {
+    #[default]
+    Normal,
+    Nowrap,
+    Pre,
+    PreWrap,
+    PreLine,
+}
+
+impl WhiteSpace {
+    pub fn from_css(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "normal" => Some(WhiteSpace::Normal),
+            "nowrap" => Some(WhiteSpace::Nowrap),
+            "pre" => Some(WhiteSpace::Pre),
+            "pre-wrap" => Some(WhiteSpace::PreWrap),
+            "pre-line" => Some(WhiteSpace::PreLine),
+            _ => None,
+        }
+    }
+
+    /// Whether runs of spaces and tabs collapse to one space.
+    pub fn collapses_spaces(self) -> bool {
+        !matches!(self, WhiteSpace::Pre | WhiteSpace::PreWrap)
+    }
+
+    /// Whether segment breaks are kept as line breaks.
+    pub fn preserves_breaks(self) -> bool {
+        !matches!(self, WhiteSpace::Normal | WhiteSpace::Nowrap)
+    }
+
+    /// Whether lines may wrap at soft wrap opportunities.
+    pub fn wraps(self) -> bool {
+        !matches!(self, WhiteSpace::Nowrap | WhiteSpace::Pre)
+    }
+}
+
+/// Spaces, tabs and segment breaks; the only characters CSS collapses.
+pub fn is_collapsible(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r' | '\u{c}')
+}
+
+/// Whether a segment break between two of these characters is removed rather
+/// than turned into a space (CSS Text, "segment break transformation").
+fn is_wide(c: char) -> bool {
+    matches!(c as u32,
+        0x2E80..=0x303E
+        | 0x3040..=0x9FFF
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x20000..=0x3FFFD)
+}
+
+/// Applies the `white-space` rules for `mode` to the text of one text node,
+/// in a single pass.
+///
+/// Leading and trailing collapsible white space is collapsed but not removed;
+/// use `trim` at the edges of a block.
+pub fn process(text: &str, mode: WhiteSpace) -> String {
+    process_into(text, mode, None)
+}
+
+/// Like `process`, and also says where every byte offset of `text` ends up:
+/// `map[i]` is the offset in the result of offset `i`, for `i` in
+/// `0..=text.len()`. An offset inside a collapsed run maps to its start.
+pub fn process_mapped(text: &str, mode: WhiteSpace) -> (String, Vec<usize>) {
+    let mut map = Vec::with_capacity(text.len() + 1);
+    let out = process_into(text, mode, Some(&mut map));
+    (out, map)
+}
+
+fn process_into(text: &str, mode: WhiteSpace, mut map: Option<&mut Vec<usize>>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mark = |map: &mut Option<&mut Vec<usize>>, c: char, pos: usize| {
+        if let Some(map) = map {
+            map.extend(std::iter::repeat_n(pos, c.len_utf8()));
+        }
+    };
+
+    if !mode.collapses_spaces() {
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            mark(&mut map, c, out.len());
+            match c {
+                '\r' => {
+                    if chars.peek() != Some(&'\n') {
+                        out.push('\n');
+                    }
+                }
+                '\u{c}' => out.push('\n'),
+                _ => out.push(c),
+            }
+        }
+        if let Some(map) = map {
+            map.push(out.len());
+        }
+        return out;
+    }
+
+    // A pending run of collapsible white space: whether it had spaces/tabs
+    // and how many segment breaks it had.
+    let mut pending_space = false;
+    let mut pending_breaks = 0usize;
+    let mut prev: Option<char> = None;
+
+    for c in text.chars() {
+        if is_collapsible(c) {
+            mark(&mut map, c, out.len());
+            if matches!(c, '\n' | '\r' | '\u{c}') {
+                // CRLF is one segment break.
+                if !(c == '\n' && prev == Some('\r')) {
+                    pending_breaks += 1;
+                }
+            } else {
+                pending_space = true;
+            }
+            prev = Some(c);
+            continue;
+        }
+
+        flush(&mut out, mode, pending_space, pending_breaks, Some(c));
+        pending_space = false;
+        pending_breaks = 0;
+        mark(&mut map, c, out.len());
+        out.push(c);
+        prev = Some(c);
+    }
+    flush(&mut out, mode, pending_space, pending_breaks, None);
+    if let Some(map) = map {
+        map.push(out.len());
+    }
+
+    out
+}
+
+fn flush(out: &mut String, mode: WhiteSpace, space: bool, breaks: usize, next: Option<char>) {
+    if !space && breaks == 0 {
+        return;
+    }
+
+    if mode.preserves_breaks() && breaks > 0 {
+        // pre-line: spaces around a break are removed.
+        for _ in 0..breaks {
+            out.push('\n');
+        }
+        return;
+    }
+
+    // Spaces next to a segment break go away first, so only the break decides.
+    if breaks > 0 {
+        let before = out.chars().next_back();
+        if let (Some(a), Some(b)) = (before, next) {
+            if is_wide(a) && is_wide(b) {
+                return;
+            }
+        }
+    }
+
+    out.push(' ');
+}
+
+/// Removes collapsible white space from both ends.
+pub fn trim(text: &str) -> &str {
+    text.trim_matches(is_collapsible)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collapse() {
+        assert_eq!(process("  a \t b\n\n c  ", WhiteSpace::Normal), " a b c ");
+        assert_eq!(
+            trim(&process("  a \t b\n c  ", WhiteSpace::Nowrap)),
+            "a b c"
+        );
+
+        // NBSP and U+3000 are not collapsible.
+        assert_eq!(
+            process("a\u{a0}\u{a0} \u{3000}b", WhiteSpace::Normal),
+            "a\u{a0}\u{a0} \u{3000}b"
+        );
+        assert_eq!(trim("\u{3000}あ "), "\u{3000}あ");
+
+        // A line break inside Japanese text disappears instead of becoming a space.
+        assert_eq!(
+            process("イングランド、\nデヴォン", WhiteSpace::Normal),
+            "イングランド、デヴォン"
+        );
+        assert_eq!(
+            process("1543年\n1596年", WhiteSpace::Normal),
+            "1543年 1596年"
+        );
+        assert_eq!(process("a \n b", WhiteSpace::Normal), "a b");
+    }
+
+    #[test]
+    fn preserve() {
+        let text = "fn main() {\r\n\tlet  a = 1;\n}";
+        assert_eq!(
+            process(text, WhiteSpace::Pre),
+            "fn main() {\n\tlet  a = 1;\n}"
+        );
+        assert_eq!(
+            process(text, WhiteSpace::PreWrap),
+            "fn main() {\n\tlet  a = 1;\n}"
+        );
+        assert_eq!(
+            process(text, WhiteSpace::PreLine),
+            "fn main() {\nlet a = 1;\n}"
+        );
+        assert_eq!(process("a  \n\n  b", WhiteSpace::PreLine), "a\n\nb");
+    }
+
+    #[test]
+    fn offsets() {
+        let (out, map) = process_mapped("a  \n bč d", WhiteSpace::Normal);
+        assert_eq!(out, "a bč d");
+        assert_eq!(map.len(), "a  \n bč d".len() + 1);
+        assert_eq!(map[1], 1);
+        assert_eq!(map[5], 2);
+        assert_eq!(map[6], 3);
+        assert_eq!(map[8], 5);
+        assert_eq!(map[10], out.len());
+
+        let (out, map) = process_mapped("x\r\ny", WhiteSpace::Pre);
+        assert_eq!(out, "x\ny");
+        assert_eq!(map, vec![0, 1, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,63 @@
+//! Cached fetching against a local stand-in for the Wikipedia server.
+#![cfg(feature = "fetch")]
+
+mod common;
+
+use francis_wb::cache::{self, Cache, CachePolicy};
+use std::sync::atomic::Ordering;
+
+const ETAG: &str = "\"rev-1\"";
+const BODY: &str = "<html><body><p>フランシス・ドレーク</p></body></html>";
+
+#[tokio::test]
+async fn revalidate_and_offline() {
+    // Serves `BODY` with an ETag and answers a matching `If-None-Match` with 304.
+    let (base, hits) = common::serve(|request| {
+        if request.header("if-none-match") == Some(ETAG) {
+            format!(
+                "HTTP/1.1 304 Not Modified\r\nETag: {}\r\nConnection: close\r\n\r\n",
+                ETAG
+            )
+        } else {
+            common::ok(
+                &format!("ETag: {}\r\n", ETAG),
+                "text/html; charset=UTF-8",
+                BODY,
+            )
+        }
+    });
+    let url = format!("{}/wiki/Drake", base);
+    let dir = std::env::temp_dir().join(format!("francis_wb_http_cache_{}", std::process::id()));
+    let cache = Cache::new(&dir).unwrap();
+    let client = reqwest::Client::new();
+
+    let first = cache::fetch(&client, &cache, &url, CachePolicy::Revalidate)
+        .await
+        .unwrap();
+    assert_eq!(first.body, BODY.as_bytes());
+    assert_eq!(first.etag(), Some(ETAG));
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    // The server answers 304 and the body comes from disk.
+    let second = cache::fetch(&client, &cache, &url, CachePolicy::Revalidate)
+        .await
+        .unwrap();
+    assert_eq!(second.body, BODY.as_bytes());
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+    let offline = cache::fetch(&client, &cache, &url, CachePolicy::Offline)
+        .await
+        .unwrap();
+    assert_eq!(offline.body, BODY.as_bytes());
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+    let missing = format!("{}/missing", url);
+    assert!(
+        cache::fetch(&client, &cache, &missing, CachePolicy::Offline)
+            .await
+            .is_err()
+    );
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,88 @@
+//! A tiny HTTP/1.1 server standing in for Wikipedia in tests.
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// A request as seen by the stand-in server. Header names are lowercase.
+pub struct Request {
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Answers every connection with `handler` and counts the requests.
+///
+/// Returns the base URL (`http://127.0.0.1:<port>`) and the request counter.
+pub fn serve<F>(handler: F) -> (String, Arc<AtomicUsize>)
+where
+    F: Fn(&Request) -> String + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = hits.clone();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            counter.fetch_add(1, Ordering::SeqCst);
+
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let path = request_line
+                .split_whitespace()
+                .nth(1)
+                .unwrap_or("/")
+                .to_string();
+
+            let mut headers = vec![];
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(i) = line.find(':') {
+                    headers.push((
+                        line[..i].trim().to_ascii_lowercase(),
+                        line[i + 1..].trim().to_string(),
+                    ));
+                }
+            }
+
+            let response = handler(&Request { path, headers });
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    (format!("http://{}", addr), hits)
+}
+
+/// A `200 OK` response with the given extra headers and body.
+pub fn ok(headers: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\n{}Content-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        headers,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+pub fn not_found() -> String {
+    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+}
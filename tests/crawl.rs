@@ -0,0 +1,61 @@
+//! Crawling a small link graph served locally.
+#![cfg(feature = "fetch")]
+
+mod common;
+
+use francis_wb::crawl::Crawler;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+fn page(links: &[&str]) -> String {
+    let anchors: String = links
+        .iter()
+        .map(|link| format!(r#"<a href="/wiki/{}">{}</a>"#, link, link))
+        .collect();
+    format!("<html><body><p>{}</p></body></html>", anchors)
+}
+
+#[tokio::test]
+async fn breadth_first_with_depth_limit() {
+    let (base, hits) = common::serve(|request| {
+        let body = match request.path.as_str() {
+            "/wiki/A" => page(&["B", "C", "Special:Random", "File:A.jpg", "A#top"]),
+            "/wiki/B" => page(&["D", "A"]),
+            "/wiki/C" => page(&["E"]),
+            "/wiki/D" => page(&["F"]),
+            _ => return common::not_found(),
+        };
+        common::ok("", "text/html; charset=utf-8", &body)
+    });
+    let dir = std::env::temp_dir().join(format!("francis_wb_crawl_{}", std::process::id()));
+
+    let started = Instant::now();
+    let report = Crawler::new("ja")
+        .base_url(&base)
+        .max_depth(2)
+        .concurrency(2)
+        .delay(Duration::from_millis(20))
+        .output_dir(&dir)
+        .run("A")
+        .await;
+
+    let visited: Vec<(&str, usize)> = report
+        .pages
+        .iter()
+        .map(|page| (page.title.as_str(), page.depth))
+        .collect();
+    assert_eq!(visited, vec![("A", 0), ("B", 1), ("C", 1), ("D", 2)]);
+
+    // E is linked from C but does not exist.
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].0, "E");
+
+    // Five requests to one host, at least 20ms apart.
+    assert_eq!(hits.load(Ordering::SeqCst), 5);
+    assert!(started.elapsed() >= Duration::from_millis(80));
+
+    assert!(dir.join("B.html").exists());
+    assert!(!dir.join("F.html").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
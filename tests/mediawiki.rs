@@ -0,0 +1,44 @@
+//! The MediaWiki client against a local stand-in for the API.
+#![cfg(feature = "fetch")]
+
+mod common;
+
+use francis_wb::mediawiki::Client;
+
+const QUERY: &str = r#"{"query": {
+    "redirects": [{"from": "ドレーク", "to": "フランシス・ドレーク"}],
+    "pages": [{
+        "pageid": 64578, "ns": 0, "title": "フランシス・ドレーク",
+        "revisions": [{"revid": 42, "parentid": 41, "timestamp": "2021-01-28T12:00:00Z"}],
+        "categories": [{"ns": 14, "title": "Category:世界一周した人物"}]
+    }]
+}}"#;
+
+const HTML: &str = r#"<!DOCTYPE html><html><head></head><body><section><table><tbody><tr><th>生誕</th><td><a href="./タヴィストック">タヴィストック</a></td></tr></tbody></table></section></body></html>"#;
+
+#[tokio::test]
+async fn article() {
+    let (base, _) = common::serve(|request| {
+        if request.path.starts_with("/w/api.php?action=query") {
+            common::ok("", "application/json", QUERY)
+        } else if request.path.starts_with("/api/rest_v1/page/html/")
+            && request.path.ends_with("/42")
+        {
+            common::ok("", "text/html; charset=utf-8", HTML)
+        } else {
+            common::not_found()
+        }
+    });
+
+    let client = Client::new("ja").with_base_url(&base);
+    let article = client.article("ドレーク").await.unwrap();
+
+    assert_eq!(article.lang, "ja");
+    assert_eq!(article.info.title, "フランシス・ドレーク");
+    assert_eq!(article.info.revision, 42);
+    assert_eq!(article.info.redirects.len(), 1);
+
+    let tables = article.parse().tables();
+    assert_eq!(tables.len(), 1);
+    assert_eq!(tables[0].cells[1].text_block.text, "タヴィストック");
+}